@@ -0,0 +1,387 @@
+use crate::pactl::Pactl;
+use crate::pipewire::PipeWire;
+use crate::{CommandRunner, WidgetError};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Volume of one channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelVolume {
+    /// Ex: front-left
+    pub channel: String,
+    pub percent: u32,
+    /// Ex: "-11.23 dB", or "-inf dB" when silent.
+    pub db: String,
+}
+
+/// A sink or source port, like headphones vs line out on the same card.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Port {
+    pub name: String,
+    pub description: String,
+    /// Ex: Headphones, Line, HDMI, Mic. Empty on old servers that don't say.
+    pub port_type: String,
+    /// False only when pulse knows it's unplugged. "availability unknown" counts as available.
+    pub available: bool,
+}
+
+impl Port {
+    /// Lowercase port type, ex "headphones". Guessed from the name on servers too old to say.
+    pub fn kind(&self) -> String {
+        if !self.port_type.is_empty() && self.port_type != "Unknown" {
+            return self.port_type.to_lowercase();
+        }
+        let name = self.name.to_lowercase();
+        // headset before mic and headphones, "analog-input-headset-mic" is a headset
+        let guesses = [
+            ("headset", "headset"),
+            ("headphone", "headphones"),
+            ("speaker", "speaker"),
+            ("hdmi", "hdmi"),
+            ("iec958", "spdif"),
+            ("spdif", "spdif"),
+            ("mic", "mic"),
+            ("line", "line"),
+        ];
+        guesses
+            .iter()
+            .find(|(part, _)| name.contains(part))
+            .map(|(_, kind)| kind.to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// One sink, source, or app's stream, from ex `pactl list sinks`. Streams have no name of their
+/// own, so theirs is the app's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Device {
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub mute: bool,
+    /// In channel map order.
+    pub volume: Vec<ChannelVolume>,
+    pub ports: Vec<Port>,
+    pub active_port: Option<String>,
+    /// For monitor sources, the sink they're monitoring.
+    pub monitor_of: Option<String>,
+    /// For streams, index of the sink it's playing on or the source it's recording from.
+    pub connected_to: Option<u32>,
+    pub properties: HashMap<String, String>,
+}
+
+impl Device {
+    /// Short name some servers give devices, ex "ALC1220 Analog". PipeWire sets it, pulse mostly
+    /// doesn't.
+    pub fn nick(&self) -> Option<&str> {
+        self.properties.get("node.nick").map(String::as_str)
+    }
+
+    /// Pulse's sources that just record what a sink plays.
+    pub fn is_monitor(&self) -> bool {
+        self.monitor_of.is_some() || self.properties.get("device.class").map(String::as_str) == Some("monitor")
+    }
+
+    /// The loudest channel's volume.
+    pub fn loudest(&self) -> u32 {
+        self.volume.iter().map(|vol| vol.percent).max().unwrap_or(0)
+    }
+
+    /// The loudest channel, which is what pulse calls the device's volume.
+    pub fn loudest_channel(&self) -> Option<&ChannelVolume> {
+        self.volume.iter().max_by_key(|vol| vol.percent)
+    }
+
+    // Average of the channels on one side, None if there aren't any
+    fn side(&self, side: &str) -> Option<f64> {
+        let side: Vec<f64> = self
+            .volume
+            .iter()
+            .filter(|vol| vol.channel.ends_with(side))
+            .map(|vol| f64::from(vol.percent))
+            .collect();
+        if side.is_empty() {
+            None
+        } else {
+            Some(side.iter().sum::<f64>() / side.len() as f64)
+        }
+    }
+
+    /// Left/right skew from -1 (all left) to 1 (all right), worked out like pulse does. 0 for
+    /// devices without both sides.
+    pub fn balance(&self) -> f64 {
+        match (self.side("left"), self.side("right")) {
+            (Some(left), Some(right)) if left > right => -(1.0 - right / left),
+            (Some(left), Some(right)) if right > left => 1.0 - left / right,
+            _ => 0.0,
+        }
+    }
+
+    /// Channel volumes for `balance`, keeping the loudest side where it is. Channels that aren't
+    /// left or right, like center, stay put.
+    pub fn balanced(&self, balance: f64) -> Vec<f64> {
+        let balance = balance.clamp(-1.0, 1.0);
+        let loudest = self.side("left").unwrap_or(0.0).max(self.side("right").unwrap_or(0.0));
+        self.volume
+            .iter()
+            .map(|vol| {
+                if vol.channel.ends_with("left") {
+                    loudest * (1.0 - balance.max(0.0))
+                } else if vol.channel.ends_with("right") {
+                    loudest * (1.0 + balance.min(0.0))
+                } else {
+                    f64::from(vol.percent)
+                }
+            })
+            .collect()
+    }
+
+    /// Channel volumes with the loudest at `percent` and the rest moved along with it, so the
+    /// balance stays.
+    pub fn scaled(&self, percent: f64) -> Vec<f64> {
+        let loudest = f64::from(self.loudest());
+        self.volume
+            .iter()
+            .map(|vol| if loudest > 0.0 { f64::from(vol.percent) * percent / loudest } else { percent })
+            .collect()
+    }
+
+    pub fn port(&self) -> Option<&Port> {
+        let active = self.active_port.as_ref()?;
+        self.ports.iter().find(|port| &port.name == active)
+    }
+
+    /// The plugged in port after the active one, wrapping around. None if there's no other.
+    pub fn next_port(&self) -> Option<&Port> {
+        let active = self.ports.iter().position(|port| Some(&port.name) == self.active_port.as_ref());
+        let start = active.map_or(0, |active| active + 1);
+        (0..self.ports.len())
+            .map(|n| &self.ports[(start + n) % self.ports.len()])
+            .find(|port| port.available && Some(&port.name) != self.active_port.as_ref())
+    }
+
+    /// False when every port is unplugged, like a monitor that's turned off. Devices without ports
+    /// are always available.
+    pub fn is_available(&self) -> bool {
+        self.ports.is_empty() || self.ports.iter().any(|port| port.available)
+    }
+}
+
+/// One way a card can be set up, like A2DP vs headset mode on bluetooth.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    /// PipeWire switches profiles by index. Just the list position on pulse, which goes by name.
+    pub index: u32,
+    /// Ex: output:analog-stereo, a2dp_sink
+    pub name: String,
+    pub description: String,
+    /// False when the card says it can't be used right now, ex HDMI with nothing plugged in.
+    pub available: bool,
+}
+
+/// The hardware behind sinks and sources. Its profile decides which of those exist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Card {
+    pub index: u32,
+    pub name: String,
+    pub profiles: Vec<Profile>,
+    pub active_profile: Option<String>,
+    pub properties: HashMap<String, String>,
+}
+
+// PipeWire says a2dp-sink where pulse says a2dp_sink, either should work in --profiles
+fn same_profile(a: &str, b: &str) -> bool {
+    a.replace('_', "-").eq_ignore_ascii_case(&b.replace('_', "-"))
+}
+
+impl Card {
+    /// Whether `dev` is one of this card's sinks or sources. Neither pactl nor pw-dump list that
+    /// directly, so this goes by PipeWire's device id, or properties they both have.
+    pub fn owns(&self, dev: &Device) -> bool {
+        if let Some(id) = dev.properties.get("device.id") {
+            return *id == self.index.to_string();
+        }
+        ["device.bus_path", "bluez.path", "api.bluez5.address", "alsa.card"]
+            .iter()
+            .find_map(|key| match (dev.properties.get(*key), self.properties.get(*key)) {
+                (Some(dev), Some(card)) => Some(dev == card),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        let active = self.active_profile.as_ref()?;
+        self.profiles.iter().find(|profile| &profile.name == active)
+    }
+
+    /// The next available profile of `wanted` after the active one, wrapping around. Starts at the
+    /// first one if the card is on something else. None if there's no other.
+    pub fn next_profile(&self, wanted: &[&str]) -> Option<&Profile> {
+        let active = self.active_profile.as_deref().unwrap_or_default();
+        let start = wanted.iter().position(|name| same_profile(name, active)).map_or(0, |n| n + 1);
+        (0..wanted.len())
+            .map(|n| wanted[(start + n) % wanted.len()])
+            .filter(|name| !same_profile(name, active))
+            .find_map(|name| self.profiles.iter().find(|profile| profile.available && same_profile(&profile.name, name)))
+    }
+}
+
+/// What a backend lists and controls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Sinks,
+    Sources,
+    /// Apps playing something.
+    SinkInputs,
+    /// Apps recording something.
+    SourceOutputs,
+}
+
+impl Target {
+    pub fn new(source: bool, streams: bool) -> Target {
+        match (source, streams) {
+            (false, false) => Target::Sinks,
+            (true, false) => Target::Sources,
+            (false, true) => Target::SinkInputs,
+            (true, true) => Target::SourceOutputs,
+        }
+    }
+
+    /// Pulse's name for one, ex "sink-input".
+    pub fn pulse_name(self) -> &'static str {
+        match self {
+            Target::Sinks => "sink",
+            Target::Sources => "source",
+            Target::SinkInputs => "sink-input",
+            Target::SourceOutputs => "source-output",
+        }
+    }
+
+    /// Sources, and the streams recording from them.
+    pub fn is_source(self) -> bool {
+        self == Target::Sources || self == Target::SourceOutputs
+    }
+
+    pub fn is_stream(self) -> bool {
+        self == Target::SinkInputs || self == Target::SourceOutputs
+    }
+}
+
+/// Something that can list and control sinks, sources or streams. `pulse-status` goes through this so the
+/// same widget works on pulse and on PipeWire without the pulse shim.
+pub trait Backend {
+    /// Everything of the `Target` the backend was opened for.
+    fn devices(&self) -> Result<Vec<Device>, WidgetError>;
+
+    /// Apps playing on the sinks, or recording from the sources. Not for streams.
+    fn streams(&self) -> Result<Vec<Device>, WidgetError>;
+
+    /// Name of the default device. Not for streams.
+    fn default_device(&self) -> Result<String, WidgetError>;
+
+    fn toggle_mute(&self, dev: &Device) -> Result<(), WidgetError>;
+
+    fn set_port(&self, dev: &Device, port: &Port) -> Result<(), WidgetError>;
+
+    fn cards(&self) -> Result<Vec<Card>, WidgetError>;
+
+    /// Switches `card` over, which can replace its sinks and sources with new ones.
+    fn set_profile(&self, card: &Card, profile: &Profile) -> Result<(), WidgetError>;
+
+    /// Same scale as pactl's percent, 100 is 0 dB.
+    fn set_volume(&self, dev: &Device, percent: f64) -> Result<(), WidgetError>;
+
+    /// Like `set_volume`, one percent per channel in the order of `dev.volume`.
+    fn set_channels(&self, dev: &Device, percents: &[f64]) -> Result<(), WidgetError>;
+
+    /// Makes `dev` the default, and moves everything playing (or recording, for sources) over to
+    /// it. Not for streams.
+    fn make_default(&self, dev: &Device) -> Result<(), WidgetError>;
+}
+
+/// A volume, or a change in volume, in pactl's percent or in dB. Parses "5%", "5", "2dB".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Volume {
+    Percent(f64),
+    Db(f64),
+}
+
+impl FromStr for Volume {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let lower = s.to_lowercase();
+        let result = match lower.strip_suffix("db") {
+            Some(db) => db.trim().parse().map(Volume::Db),
+            None => s.trim_end_matches('%').trim().parse().map(Volume::Percent),
+        };
+        result.map_err(|_| format!("`{}` isn't a volume like 5% or 2dB", s))
+    }
+}
+
+impl Volume {
+    /// As a volume in percent. 0dB is 100%.
+    pub fn percent(self) -> f64 {
+        match self {
+            Volume::Percent(percent) => percent,
+            // Volumes are cubic, so dB is 60 * log10 of the percent
+            Volume::Db(db) => 100.0 * 10f64.powf(db / 60.0),
+        }
+    }
+
+    /// `current` percent raised, or lowered when `!up`, by this as a step. Never below 0.
+    pub fn step(self, current: f64, up: bool) -> f64 {
+        let target = match self {
+            Volume::Percent(step) if up => current + step.abs(),
+            Volume::Percent(step) => current - step.abs(),
+            // dB from silence is still silence, so get it off the floor first
+            Volume::Db(step) if up => current.max(1.0) * 10f64.powf(step.abs() / 60.0),
+            Volume::Db(step) => current * 10f64.powf(-step.abs() / 60.0),
+        };
+        target.max(0.0)
+    }
+
+    pub fn times(self, factor: f64) -> Volume {
+        match self {
+            Volume::Percent(percent) => Volume::Percent(percent * factor),
+            Volume::Db(db) => Volume::Db(db * factor),
+        }
+    }
+}
+
+/// Which `Backend` to use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackendKind {
+    /// PipeWire if it's running, pulse otherwise.
+    Auto,
+    /// `pactl`. Also works on PipeWire through pipewire-pulse.
+    Pulse,
+    /// `pw-dump` and `wpctl`.
+    PipeWire,
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(BackendKind::Auto),
+            "pulse" => Ok(BackendKind::Pulse),
+            "pipewire" => Ok(BackendKind::PipeWire),
+            _ => Err(format!("Unknown backend `{}`", s)),
+        }
+    }
+}
+
+impl BackendKind {
+    pub fn open<'a>(self, target: Target, runner: &'a dyn CommandRunner) -> Box<dyn Backend + 'a> {
+        match self {
+            BackendKind::Pulse => Box::new(Pactl::new(target, runner)),
+            BackendKind::PipeWire => Box::new(PipeWire::new(target, runner)),
+            BackendKind::Auto => match PipeWire::probe(target, runner) {
+                Some(pipewire) => Box::new(pipewire),
+                None => Box::new(Pactl::new(target, runner)),
+            },
+        }
+    }
+}
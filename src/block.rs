@@ -0,0 +1,353 @@
+use crate::error::WidgetError;
+use clap::Clap;
+use serde::{Serialize, Serializer};
+use std::str::FromStr;
+
+/// Which of i3blocks' output formats to print. Has to match the `format=` key of the block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Plain lines. i3blocks' default.
+    Legacy,
+    /// One JSON object per block, for `format=json`.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(Format::Legacy),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format `{}`", s)),
+        }
+    }
+}
+
+/// Text alignment inside a block that's wider than its text, see `min_width`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl FromStr for Align {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Align::Left),
+            "center" => Ok(Align::Center),
+            "right" => Ok(Align::Right),
+            _ => Err(format!("Unknown alignment `{}`", s)),
+        }
+    }
+}
+
+/// A single i3blocks block. Every widget fills one of these out and prints it, rather than
+/// hand-writing the long/short/color lines itself.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Block {
+    pub full_text: String,
+    /// Shown instead of `full_text` when the bar is too long. Falls back to `full_text`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<String>,
+    /// Either a pixel count or a sample string to measure the width from.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "min_width_json")]
+    pub min_width: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub align: Option<Align>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub urgent: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub separator: Option<bool>,
+    /// "pango" or "none"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub markup: Option<String>,
+}
+
+// i3bar wants min_width as a number when it's pixels, and a string when it's sample text.
+fn min_width_json<S: Serializer>(min_width: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    match min_width.as_deref().map(|w| w.parse::<u32>()) {
+        Some(Ok(pixels)) => s.serialize_u32(pixels),
+        _ => min_width.serialize(s),
+    }
+}
+
+impl Block {
+    pub fn new(full_text: &str) -> Block {
+        Block {
+            full_text: full_text.to_string(),
+            ..Block::default()
+        }
+    }
+
+    /// Block showing `text` in the error color. Used for every failure so they all look the same.
+    pub fn error(text: &str, color: &str) -> Block {
+        let mut block = Block::new(text);
+        block.color = Some(color.to_string());
+        block
+    }
+
+    /// i3blocks' plain text format. One line each for full text, short text, color and
+    /// background. Trailing lines are left off if they aren't set.
+    pub fn to_legacy(&self) -> String {
+        let mut lines = vec![
+            self.full_text.as_str(),
+            self.short_text.as_deref().unwrap_or(&self.full_text),
+        ];
+        if self.color.is_some() || self.background.is_some() {
+            lines.push(self.color.as_deref().unwrap_or(""));
+        }
+        if let Some(background) = &self.background {
+            lines.push(background);
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        // Can't fail, everything in a block is a plain string, bool, or number.
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Legacy => self.to_legacy(),
+            Format::Json => self.to_json(),
+        }
+    }
+
+    /// One line per update for `--persist`. Plain persistent blocks only take full text, anything
+    /// past the first line would show up as a block of its own.
+    pub fn render_persistent(&self, format: Format) -> String {
+        match format {
+            Format::Legacy => self.full_text.clone(),
+            Format::Json => self.to_json(),
+        }
+    }
+
+    /// i3blocks reads an exit code of 33 as "mark this block urgent".
+    pub fn exit_code(&self) -> i32 {
+        if self.urgent {
+            33
+        } else {
+            0
+        }
+    }
+
+    pub fn print(&self, format: Format) {
+        println!("{}", self.render(format));
+    }
+
+    /// Prints the block and exits. Returns `!` so it can be used as a `fail(..)` match arm.
+    pub fn finish(&self, format: Format) -> ! {
+        self.print(format);
+        std::process::exit(self.exit_code());
+    }
+}
+
+/// Options every widget takes. Flatten into the widget's `Opts` with `#[clap(flatten)]`.
+/// Border, width, align, separator and markup only show up with `--format json`.
+#[derive(Clap, Clone, Debug)]
+pub struct BlockOpts {
+    /// Output format. Use `json` with i3blocks' `format=json`.
+    #[clap(long, default_value = "legacy", possible_values = &["legacy", "json"])]
+    pub format: Format,
+
+    #[clap(long, default_value = "#dc322f")]
+    pub color_error: String,
+
+    #[clap(long)]
+    pub background_error: Option<String>,
+
+    /// Border color.
+    #[clap(long)]
+    pub border: Option<String>,
+
+    /// Minimum width in pixels, or a sample text to take the width from.
+    #[clap(long)]
+    pub min_width: Option<String>,
+
+    /// Text alignment when the block is wider than its text.
+    #[clap(long, possible_values = &["left", "center", "right"])]
+    pub align: Option<Align>,
+
+    /// Disables the separator after the block.
+    #[clap(long)]
+    pub no_separator: bool,
+
+    /// Pango markup in the text. `pango` or `none`.
+    #[clap(long)]
+    pub markup: Option<String>,
+
+    /// Keep running and print a new block whenever it changes. For `interval=persist`.
+    #[clap(long)]
+    pub persist: bool,
+
+    /// Seconds between updates with `--persist`.
+    #[clap(long, default_value = "1")]
+    pub poll: f32,
+}
+
+impl BlockOpts {
+    /// Fills in the display options the widget itself doesn't care about.
+    pub fn apply(&self, block: &mut Block) {
+        if block.border.is_none() {
+            block.border = self.border.clone();
+        }
+        if block.min_width.is_none() {
+            block.min_width = self.min_width.clone();
+        }
+        if block.align.is_none() {
+            block.align = self.align;
+        }
+        if self.no_separator {
+            block.separator = Some(false);
+        }
+        if block.markup.is_none() {
+            block.markup = self.markup.clone();
+        }
+    }
+
+    /// Urgent block in the error color. Every failure gets shown through this, panics included.
+    pub fn error_block(&self, text: &str) -> Block {
+        let mut block = Block::error(text, &self.color_error);
+        block.background = self.background_error.clone();
+        block.urgent = true;
+        block
+    }
+
+    /// Prints the widget's block, or its error, in the chosen format and exits.
+    pub fn finish(&self, result: Result<Block, WidgetError>) -> ! {
+        match result {
+            Ok(block) => self.show(block),
+            Err(err) => self.show(self.error_block(&err.to_string())),
+        }
+    }
+
+    fn show(&self, mut block: Block) -> ! {
+        self.apply(&mut block);
+        if self.persist {
+            println!("{}", block.render_persistent(self.format));
+            std::process::exit(block.exit_code());
+        }
+        block.finish(self.format)
+    }
+
+    /// Shows panics as an error block instead of leaving i3blocks with a backtrace.
+    pub fn catch_panics(&self) {
+        let opts = self.clone();
+        std::panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let msg = match payload.downcast_ref::<&str>() {
+                Some(msg) => msg.to_string(),
+                None => match payload.downcast_ref::<String>() {
+                    Some(msg) => msg.clone(),
+                    None => String::from("unknown"),
+                },
+            };
+            opts.show(opts.error_block(&format!("Panic: {}", msg)))
+        }));
+    }
+}
+
+#[cfg(test)]
+mod block_tests {
+    use super::*;
+    #[test]
+    fn legacy_full_only() {
+        assert_eq!(Block::new("hi").to_legacy(), "hi\nhi");
+    }
+
+    #[test]
+    fn legacy_short() {
+        let mut block = Block::new("hello there");
+        block.short_text = Some(String::from("hi"));
+        assert_eq!(block.to_legacy(), "hello there\nhi");
+    }
+
+    #[test]
+    fn legacy_color() {
+        assert_eq!(Block::error("oops", "#dc322f").to_legacy(), "oops\noops\n#dc322f");
+    }
+
+    #[test]
+    fn legacy_background_no_color() {
+        let mut block = Block::new("hi");
+        block.background = Some(String::from("#002b36"));
+        assert_eq!(block.to_legacy(), "hi\nhi\n\n#002b36");
+    }
+
+    #[test]
+    fn persistent_one_line() {
+        let block = Block::error("oops", "#dc322f");
+        assert_eq!(block.render_persistent(Format::Legacy), "oops");
+        assert_eq!(block.render_persistent(Format::Json), r##"{"full_text":"oops","color":"#dc322f"}"##);
+    }
+
+    #[test]
+    fn json_full_only() {
+        assert_eq!(Block::new("hi").to_json(), r#"{"full_text":"hi"}"#);
+    }
+
+    #[test]
+    fn json_all() {
+        let block = Block {
+            full_text: String::from("hello \"there\""),
+            short_text: Some(String::from("hi")),
+            color: Some(String::from("#dc322f")),
+            background: Some(String::from("#002b36")),
+            border: Some(String::from("#268bd2")),
+            min_width: Some(String::from("100")),
+            align: Some(Align::Center),
+            urgent: true,
+            separator: Some(false),
+            markup: Some(String::from("pango")),
+        };
+        assert_eq!(
+            block.to_json(),
+            r##"{"full_text":"hello \"there\"","short_text":"hi","color":"#dc322f","background":"#002b36","border":"#268bd2","min_width":100,"align":"center","urgent":true,"separator":false,"markup":"pango"}"##
+        );
+    }
+
+    #[test]
+    fn json_min_width_text() {
+        let mut block = Block::new("hi");
+        block.min_width = Some(String::from("GPU 100%"));
+        assert_eq!(block.to_json(), r#"{"full_text":"hi","min_width":"GPU 100%"}"#);
+    }
+
+    #[test]
+    fn opts_keep_widget_values() {
+        let opts = BlockOpts::parse_from(["widget", "--border", "#ffffff", "--no-separator"]);
+        let mut block = Block::new("hi");
+        block.border = Some(String::from("#000000"));
+        opts.apply(&mut block);
+        assert_eq!(block.border, Some(String::from("#000000")));
+        assert_eq!(block.separator, Some(false));
+        assert_eq!(opts.format, Format::Legacy);
+    }
+
+    #[test]
+    fn error_block_urgent() {
+        let opts = BlockOpts::parse_from(["widget", "--background-error", "#000000"]);
+        let block = opts.error_block("oops");
+        assert_eq!(block.color, Some(String::from("#dc322f")));
+        assert_eq!(block.background, Some(String::from("#000000")));
+        assert_eq!(block.exit_code(), 33);
+    }
+
+    #[test]
+    fn urgent_exit_code() {
+        let mut block = Block::new("hi");
+        assert_eq!(block.exit_code(), 0);
+        block.urgent = true;
+        assert_eq!(block.exit_code(), 33);
+    }
+}
@@ -0,0 +1,200 @@
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// A mouse click on a block, as i3blocks reports it.
+/// Button numbers are 1 = LMB, 2 = MMB, 3 = RMB, 4 = ScrollUp, 5 = ScrollDown.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ClickEvent {
+    pub button: i32,
+    /// Held modifier keys, named the way i3 names them. "Shift", "Control", "Mod1"...
+    #[serde(default)]
+    pub modifiers: Vec<String>,
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    /// Click position inside the block itself.
+    #[serde(default)]
+    pub relative_x: i32,
+    #[serde(default)]
+    pub relative_y: i32,
+    /// Size of the block.
+    #[serde(default)]
+    pub width: i32,
+    #[serde(default)]
+    pub height: i32,
+}
+
+impl ClickEvent {
+    /// Click with nothing but a button, like the positional `$button` arg gives.
+    pub fn button(button: i32) -> ClickEvent {
+        ClickEvent {
+            button,
+            ..ClickEvent::default()
+        }
+    }
+
+    /// Parses one line of i3blocks click input. That's a JSON object in `format=json`, otherwise
+    /// just the button number.
+    pub fn from_line(line: &str) -> Option<ClickEvent> {
+        let line = line.trim();
+        if line.starts_with('{') {
+            serde_json::from_str(line).ok()
+        } else {
+            line.parse().ok().map(ClickEvent::button)
+        }
+    }
+
+    /// Reads the click from i3blocks' environment variables, if there was one.
+    pub fn from_env() -> Option<ClickEvent> {
+        ClickEvent::from_vars(|name| std::env::var(name).ok())
+    }
+
+    // Older i3blocks uses BLOCK_BUTTON etc, 1.5 uses the plain lowercase names.
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Option<ClickEvent> {
+        let get = |name: &str| var(&format!("BLOCK_{}", name.to_uppercase())).or_else(|| var(name));
+        let num = |name: &str| get(name).and_then(|val| val.trim().parse().ok()).unwrap_or(0);
+
+        let button = get("button")?.trim().parse().ok()?;
+        let modifiers = match get("modifiers") {
+            // Could be "Shift,Mod4" or a JSON array depending on version, so just grab the words.
+            Some(val) => val
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|m| !m.is_empty())
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Some(ClickEvent {
+            button,
+            modifiers,
+            x: num("x"),
+            y: num("y"),
+            relative_x: num("relative_x"),
+            relative_y: num("relative_y"),
+            width: num("width"),
+            height: num("height"),
+        })
+    }
+
+    /// The click for this run. A `$button` passed on the command line wins over the environment.
+    pub fn get(button: Option<i32>) -> Option<ClickEvent> {
+        match button {
+            Some(button) => Some(ClickEvent::button(button)),
+            None => ClickEvent::from_env(),
+        }
+    }
+
+    pub fn has_modifier(&self, modifier: &str) -> bool {
+        self.modifiers.iter().any(|m| m.eq_ignore_ascii_case(modifier))
+    }
+}
+
+/// A button plus held modifiers that triggers some action, like "Shift+1" or just "2". Also takes
+/// the usual names for i3's modifiers, Ctrl, Alt and Super.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClickBinding {
+    pub button: i32,
+    /// Named like i3 names them.
+    pub modifiers: Vec<String>,
+}
+
+impl FromStr for ClickBinding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let button = parts
+            .pop()
+            .and_then(|button| button.parse().ok())
+            .ok_or_else(|| format!("`{}` doesn't end in a button number, ex Shift+1", s))?;
+        let modifiers = parts
+            .iter()
+            .map(|modifier| match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => String::from("Control"),
+                "alt" => String::from("Mod1"),
+                "super" | "win" => String::from("Mod4"),
+                _ => modifier.to_string(),
+            })
+            .collect();
+        Ok(ClickBinding { button, modifiers })
+    }
+}
+
+impl ClickBinding {
+    /// Right button with at least these modifiers held.
+    pub fn matches(&self, click: &ClickEvent) -> bool {
+        click.button == self.button && self.modifiers.iter().all(|modifier| click.has_modifier(modifier))
+    }
+}
+
+#[cfg(test)]
+mod click_tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn vars(pairs: &[(&str, &str)]) -> Option<ClickEvent> {
+        let map: HashMap<String, String> =
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        ClickEvent::from_vars(|name| map.get(name).cloned())
+    }
+
+    #[test]
+    fn json_line() {
+        let click = ClickEvent::from_line(
+            r#"{"name":"pulse","button":4,"modifiers":["Shift"],"x":1800,"y":10,"relative_x":12,"relative_y":10,"width":80,"height":22}"#,
+        )
+        .unwrap();
+        assert_eq!(click.button, 4);
+        assert!(click.has_modifier("shift"));
+        assert!(!click.has_modifier("Control"));
+        assert_eq!((click.relative_x, click.width), (12, 80));
+    }
+
+    #[test]
+    fn json_line_minimal() {
+        assert_eq!(ClickEvent::from_line(r#"{"button":1}"#), Some(ClickEvent::button(1)));
+    }
+
+    #[test]
+    fn plain_line() {
+        assert_eq!(ClickEvent::from_line("3\n"), Some(ClickEvent::button(3)));
+    }
+
+    #[test]
+    fn bad_line() {
+        assert_eq!(ClickEvent::from_line("{button"), None);
+        assert_eq!(ClickEvent::from_line(""), None);
+    }
+
+    #[test]
+    fn env_block_vars() {
+        let click = vars(&[("BLOCK_BUTTON", "5"), ("BLOCK_X", "100"), ("BLOCK_RELATIVE_X", "7")]).unwrap();
+        assert_eq!((click.button, click.x, click.relative_x), (5, 100, 7));
+    }
+
+    #[test]
+    fn env_lowercase_vars() {
+        let click = vars(&[("button", "1"), ("modifiers", "Shift,Mod4"), ("width", "50")]).unwrap();
+        assert_eq!(click.modifiers, vec!["Shift", "Mod4"]);
+        assert_eq!(click.width, 50);
+    }
+
+    #[test]
+    fn env_no_click() {
+        assert_eq!(vars(&[("BLOCK_X", "100")]), None);
+        assert_eq!(vars(&[("BLOCK_BUTTON", "")]), None);
+    }
+
+    #[test]
+    fn bindings() {
+        let binding: ClickBinding = "ctrl + Shift+1".parse().unwrap();
+        assert_eq!(binding.modifiers, vec!["Control", "Shift"]);
+        let mut click = ClickEvent::button(1);
+        assert!(!binding.matches(&click));
+        click.modifiers = vec![String::from("Shift"), String::from("Control"), String::from("Mod2")];
+        assert!(binding.matches(&click));
+        assert!("3".parse::<ClickBinding>().unwrap().matches(&ClickEvent::button(3)));
+        assert!("Shift+".parse::<ClickBinding>().is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// An i3bar color, `#rrggbb` or `#rrggbbaa`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Only set if the color had one, so it prints back the same way.
+    pub a: Option<u8>,
+}
+
+impl FromStr for Color {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().strip_prefix('#').unwrap_or_default();
+        let byte = |n: usize| u8::from_str_radix(hex.get(n * 2..n * 2 + 2).unwrap_or_default(), 16).ok();
+        let color = match hex.len() {
+            6 => byte(0)
+                .zip(byte(1))
+                .zip(byte(2))
+                .map(|((r, g), b)| Color { r, g, b, a: None }),
+            8 => byte(0)
+                .zip(byte(1))
+                .zip(byte(2))
+                .zip(byte(3))
+                .map(|(((r, g), b), a)| Color { r, g, b, a: Some(a) }),
+            _ => None,
+        };
+        color.ok_or_else(|| format!("`{}` isn't a color, ex #268bd2 or #268bd280", s))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        match self.a {
+            Some(a) => write!(f, "{:02x}", a),
+            None => Ok(()),
+        }
+    }
+}
+
+// sRGB's gamma, both ways
+fn linear(channel: u8) -> f32 {
+    let c = f32::from(channel) / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn gamma(c: f32) -> u8 {
+    let c = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// Björn Ottosson's Oklab. Straight RGB mixes go muddy and dark in the middle, this stays even.
+fn to_oklab(color: Color) -> [f32; 3] {
+    let (r, g, b) = (linear(color.r), linear(color.g), linear(color.b));
+    let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn from_oklab([lightness, a, b]: [f32; 3]) -> (u8, u8, u8) {
+    let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m = (lightness - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+    (
+        gamma(4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s),
+        gamma(-1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s),
+        gamma(-0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s),
+    )
+}
+
+impl Color {
+    /// The color `t` of the way from `self` to `other`, 0 to 1, mixed in Oklab.
+    pub fn mix(self, other: Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let (from, to) = (to_oklab(self), to_oklab(other));
+        let mut lab = [0.0; 3];
+        for n in 0..3 {
+            lab[n] = from[n] + (to[n] - from[n]) * t;
+        }
+        let (r, g, b) = from_oklab(lab);
+        let a = match (self.a, other.a) {
+            (None, None) => None,
+            (a1, a2) => {
+                let (a1, a2) = (f32::from(a1.unwrap_or(255)), f32::from(a2.unwrap_or(255)));
+                Some((a1 + (a2 - a1) * t).round() as u8)
+            }
+        };
+        Color { r, g, b, a }
+    }
+}
+
+/// Colors at points along some value, like `0:#268bd2,60:#b58900,85:#dc322f` for a percent.
+/// Values in between get a mix of the stops around them, values past the ends the end color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    /// Sorted by value.
+    stops: Vec<(f32, Color)>,
+}
+
+impl FromStr for Gradient {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut stops = s
+            .split(',')
+            .map(|stop| {
+                let (value, color) = stop
+                    .split_once(':')
+                    .ok_or_else(|| format!("`{}` should be value:color, ex 60:#b58900", stop.trim()))?;
+                let value = value
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|_| format!("`{}` isn't a number", value.trim()))?;
+                Ok((value, color.parse()?))
+            })
+            .collect::<Result<Vec<(f32, Color)>, String>>()?;
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(Gradient { stops })
+    }
+}
+
+impl Gradient {
+    pub fn at(&self, value: f32) -> Color {
+        // Parsing always gives at least one stop
+        let (first, last) = (self.stops[0], self.stops[self.stops.len() - 1]);
+        if value <= first.0 {
+            return first.1;
+        }
+        if value >= last.0 {
+            return last.1;
+        }
+        let after = self.stops.iter().position(|stop| stop.0 > value).unwrap_or(self.stops.len() - 1);
+        let (from, to) = (self.stops[after - 1], self.stops[after]);
+        from.1.mix(to.1, (value - from.0) / (to.0 - from.0))
+    }
+
+    /// `at` as an i3bar color string.
+    pub fn color(&self, value: f32) -> String {
+        self.at(value).to_string()
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        let color: Color = "#268bd2".parse().unwrap();
+        assert_eq!(color, Color { r: 0x26, g: 0x8b, b: 0xd2, a: None });
+        assert_eq!(color.to_string(), "#268bd2");
+        assert_eq!("#268BD280".parse::<Color>().unwrap().to_string(), "#268bd280");
+        assert!("268bd2".parse::<Color>().is_err());
+        assert!("#268bd".parse::<Color>().is_err());
+        assert!("#26xbd2".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for hex in ["#000000", "#ffffff", "#268bd2", "#b58900", "#dc322f", "#859900"] {
+            let color: Color = hex.parse().unwrap();
+            assert_eq!(color.mix(color, 0.5).to_string(), hex);
+        }
+    }
+
+    #[test]
+    fn mixing() {
+        let black: Color = "#000000".parse().unwrap();
+        let white: Color = "#ffffff".parse().unwrap();
+        assert_eq!(black.mix(white, 0.0), black);
+        assert_eq!(black.mix(white, 1.0), white);
+        // Halfway in Oklab lightness is darker than the RGB average of 128
+        let gray = black.mix(white, 0.5);
+        assert_eq!((gray.r, gray.g, gray.b), (99, 99, 99));
+        let clear: Color = "#ffffff00".parse().unwrap();
+        assert_eq!(white.mix(clear, 0.5).a, Some(128));
+    }
+
+    #[test]
+    fn gradient_stops() {
+        let gradient: Gradient = "60:#b58900, 0:#268bd2,85:#dc322f".parse().unwrap();
+        assert_eq!(gradient.color(-5.0), "#268bd2");
+        assert_eq!(gradient.color(0.0), "#268bd2");
+        assert_eq!(gradient.color(60.0), "#b58900");
+        assert_eq!(gradient.color(150.0), "#dc322f");
+        let blue: Color = "#268bd2".parse().unwrap();
+        assert_eq!(gradient.at(30.0), blue.mix("#b58900".parse().unwrap(), 0.5));
+        assert!("0:#268bd2,60".parse::<Gradient>().is_err());
+        assert!("x:#268bd2".parse::<Gradient>().is_err());
+        assert!("".parse::<Gradient>().is_err());
+    }
+}
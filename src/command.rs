@@ -0,0 +1,349 @@
+use crate::error::WidgetError;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a program gets to run before it's killed, unless the `Command` says otherwise.
+/// i3blocks won't redraw a block until its command exits, so a hung `nvidia-smi` would otherwise
+/// freeze the block for good.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long past the timeout the output still gets waited on
+const PIPE_GRACE: Duration = Duration::from_millis(100);
+
+/// Everything a finished command gave back.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandOutput {
+    /// None if the program was killed by a signal, including by the timeout.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+/// An external program to run. Like `std::process::Command`, but with a timeout and output that's
+/// always text, even when the program prints invalid UTF-8.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Command {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Extra environment variables, on top of the inherited ones.
+    pub env: Vec<(String, String)>,
+    /// `DEFAULT_TIMEOUT` unless set. None waits forever.
+    pub timeout: Option<Duration>,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+impl Command {
+    pub fn new(program: &str) -> Command {
+        Command {
+            program: program.to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+            timeout: Some(DEFAULT_TIMEOUT),
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Command {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args(mut self, args: &[&str]) -> Command {
+        self.args.extend(args.iter().map(|arg| arg.to_string()));
+        self
+    }
+
+    /// Ex: `.env("LC_ALL", "C")` so the output doesn't depend on the user's language.
+    pub fn env(mut self, key: &str, val: &str) -> Command {
+        self.env.push((key.to_string(), val.to_string()));
+        self
+    }
+
+    /// Kill the program if it's still running after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Command {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn spawn(&self, stderr: Stdio) -> Result<Child, WidgetError> {
+        std::process::Command::new(&self.program)
+            .args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(stderr)
+            .spawn()
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => WidgetError::CommandNotFound(self.program.clone()),
+                _ => WidgetError::CommandFailed {
+                    command: self.to_string(),
+                    stderr: err.to_string(),
+                },
+            })
+    }
+
+    /// Runs the program and waits for it. Only errors if it couldn't be started at all, a failed
+    /// or timed out run still gives the output.
+    pub fn output(&self) -> Result<CommandOutput, WidgetError> {
+        let mut child = self.spawn(Stdio::piped())?;
+
+        // Pipes have to be drained while waiting, or a chatty program blocks on a full pipe.
+        let drain = |pipe: Option<Box<dyn Read + Send>>| {
+            let (tx, rx) = channel();
+            thread::spawn(move || {
+                let mut bytes = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    // A read error just means we get whatever came before it.
+                    let _ = pipe.read_to_end(&mut bytes);
+                }
+                let _ = tx.send(String::from_utf8_lossy(&bytes).to_string());
+            });
+            rx
+        };
+        let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+        let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>));
+
+        let start = Instant::now();
+        let mut timed_out = false;
+        let status = match self.timeout {
+            None => child.wait(),
+            Some(timeout) => loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => break Ok(status),
+                    Ok(None) if start.elapsed() >= timeout => {
+                        timed_out = true;
+                        let _ = child.kill();
+                        break child.wait();
+                    }
+                    Ok(None) => thread::sleep(Duration::from_millis(10)),
+                    Err(err) => break Err(err),
+                }
+            },
+        };
+
+        // Anything the program started, like whatever `sh -c` runs, can keep the pipes open after
+        // it's gone. So only wait for them until the timeout, or a moment longer if it ran out.
+        let until = self
+            .timeout
+            .map(|timeout| (start + timeout).max(Instant::now()) + PIPE_GRACE);
+        let collect = |pipe: Receiver<String>| match until {
+            Some(until) => pipe.recv_timeout(until.saturating_duration_since(Instant::now())),
+            None => pipe.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        Ok(CommandOutput {
+            code: status.ok().and_then(|status| status.code()),
+            stdout: collect(stdout).unwrap_or_default(),
+            stderr: collect(stderr).unwrap_or_default(),
+            timed_out,
+        })
+    }
+
+    /// For programs that keep running, like `pactl subscribe`. Hands each line of output to
+    /// `on_line` until it returns false or the program exits, then makes sure the program is dead.
+    /// Ignores the timeout.
+    pub fn lines<F: FnMut(&str) -> bool>(&self, mut on_line: F) -> Result<(), WidgetError> {
+        let mut child = self.spawn(Stdio::null())?;
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) if on_line(&line) => (),
+                    _ => break,
+                }
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+        Ok(())
+    }
+
+    /// `lines` for binary output, like audio from `parec`. Hands over `size` bytes at a time, less
+    /// only for the last bit before the program exits.
+    pub fn chunks<F: FnMut(&[u8]) -> bool>(&self, size: usize, mut on_chunk: F) -> Result<(), WidgetError> {
+        let mut child = self.spawn(Stdio::null())?;
+        if let Some(mut stdout) = child.stdout.take() {
+            let mut chunk = vec![0; size.max(1)];
+            loop {
+                // read_exact would throw away a short last chunk
+                let mut filled = 0;
+                while filled < chunk.len() {
+                    match stdout.read(&mut chunk[filled..]) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => filled += read,
+                    }
+                }
+                if filled == 0 || !on_chunk(&chunk[..filled]) || filled < chunk.len() {
+                    break;
+                }
+            }
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+        Ok(())
+    }
+
+    /// Trimmed stdout of a successful run. Failing, timing out or printing nothing are all errors.
+    pub fn run(&self) -> Result<String, WidgetError> {
+        self.check(self.output()?)
+    }
+
+    /// The `run` half that decides whether `output`, from running this command, was a success.
+    pub fn check(&self, output: CommandOutput) -> Result<String, WidgetError> {
+        if output.timed_out {
+            return Err(WidgetError::CommandTimedOut(self.to_string()));
+        }
+        let stdout = output.stdout.trim();
+        if !output.success() || stdout.is_empty() {
+            return Err(WidgetError::CommandFailed {
+                command: self.to_string(),
+                stderr: output.stderr.trim().to_string(),
+            });
+        }
+        Ok(stdout.to_string())
+    }
+}
+
+#[cfg(test)]
+mod command_tests {
+    use super::*;
+    #[test]
+    fn output_fields() {
+        let output = Command::new("sh")
+            .args(&["-c", "echo hi; echo there >&2; exit 3"])
+            .output()
+            .unwrap();
+        assert_eq!(output.code, Some(3));
+        assert_eq!(output.stdout, "hi\n");
+        assert_eq!(output.stderr, "there\n");
+        assert!(!output.success());
+        assert!(!output.timed_out);
+    }
+
+    #[test]
+    fn run_trims() {
+        assert_eq!(Command::new("echo").arg(" hi ").run(), Ok(String::from("hi")));
+    }
+
+    #[test]
+    fn run_nonzero() {
+        let err = Command::new("sh")
+            .args(&["-c", "echo nope >&2; exit 1"])
+            .run()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            WidgetError::CommandFailed {
+                command: String::from("sh -c echo nope >&2; exit 1"),
+                stderr: String::from("nope"),
+            }
+        );
+    }
+
+    #[test]
+    fn run_empty() {
+        assert!(Command::new("true").run().is_err());
+    }
+
+    #[test]
+    fn not_found() {
+        assert_eq!(
+            Command::new("echoooo").output(),
+            Err(WidgetError::CommandNotFound(String::from("echoooo")))
+        );
+    }
+
+    #[test]
+    fn timeout_kills() {
+        let start = Instant::now();
+        let cmd = Command::new("sleep").arg("5").timeout(Duration::from_millis(100));
+        let output = cmd.output().unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.code, None);
+        assert!(start.elapsed() < Duration::from_secs(4));
+        assert_eq!(cmd.run(), Err(WidgetError::CommandTimedOut(String::from("sleep 5"))));
+    }
+
+    #[test]
+    fn timeout_with_children() {
+        // sh gets killed, but the sleep it started still holds the pipes
+        let start = Instant::now();
+        let output = Command::new("sh")
+            .args(&["-c", "sleep 5"])
+            .timeout(Duration::from_millis(100))
+            .output()
+            .unwrap();
+        assert!(output.timed_out);
+        assert!(start.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn lines_until_false() {
+        let mut seen = Vec::new();
+        let cmd = Command::new("sh").args(&["-c", "echo a; echo b; echo c; sleep 5"]);
+        let start = Instant::now();
+        cmd.lines(|line| {
+            seen.push(line.to_string());
+            line != "b"
+        })
+        .unwrap();
+        assert_eq!(seen, vec!["a", "b"]);
+        assert!(start.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn lines_until_exit() {
+        let mut seen = Vec::new();
+        Command::new("printf")
+            .arg(r"a\nb\n")
+            .lines(|line| {
+                seen.push(line.to_string());
+                true
+            })
+            .unwrap();
+        assert_eq!(seen, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn chunks_of_size() {
+        let mut seen = Vec::new();
+        Command::new("printf")
+            .arg("abcdefg")
+            .chunks(3, |chunk| {
+                seen.push(String::from_utf8_lossy(chunk).to_string());
+                true
+            })
+            .unwrap();
+        assert_eq!(seen, vec!["abc", "def", "g"]);
+    }
+
+    #[test]
+    fn env_override() {
+        let cmd = Command::new("sh").args(&["-c", "echo $LC_ALL"]).env("LC_ALL", "C");
+        assert_eq!(cmd.run(), Ok(String::from("C")));
+    }
+
+    #[test]
+    fn lossy_utf8() {
+        let output = Command::new("printf").arg(r"a\377b").output().unwrap();
+        assert_eq!(output.stdout, "a\u{FFFD}b");
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+/// Everything that can go wrong in a widget. All of them end up as the same urgent error block,
+/// see `BlockOpts::finish`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WidgetError {
+    /// Program isn't installed or isn't on $PATH.
+    CommandNotFound(String),
+    /// Program ran, but failed or printed nothing useful.
+    CommandFailed { command: String, stderr: String },
+    /// Program was killed for taking too long. Holds the full command line.
+    CommandTimedOut(String),
+    /// Output didn't have what we were looking for. Holds the name of that thing.
+    Parse(String),
+    /// The requested sink/source/GPU/etc doesn't exist.
+    DeviceNotFound(String),
+    /// A device search matched more than one. Holds the search and the names it matched.
+    AmbiguousDevice { id: String, candidates: Vec<String> },
+}
+
+impl fmt::Display for WidgetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WidgetError::CommandNotFound(cmd) => write!(f, "`{}` not found.", cmd),
+            WidgetError::CommandFailed { command, stderr } => match stderr.lines().next() {
+                // Only the first line, the block has to fit in the bar.
+                Some(line) => write!(f, "`{}` failed: {}", command, line.trim()),
+                None => write!(f, "`{}` failed.", command),
+            },
+            WidgetError::CommandTimedOut(cmd) => write!(f, "`{}` timed out.", cmd),
+            WidgetError::Parse(what) => write!(f, "Couldn't find {}.", what),
+            WidgetError::DeviceNotFound(dev) => write!(f, "Device {} not found.", dev),
+            WidgetError::AmbiguousDevice { id, candidates } => {
+                write!(f, "`{}` matches {}.", id, candidates.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for WidgetError {}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+    #[test]
+    fn display() {
+        assert_eq!(WidgetError::CommandNotFound(String::from("pactl")).to_string(), "`pactl` not found.");
+        assert_eq!(WidgetError::Parse(String::from("utilization")).to_string(), "Couldn't find utilization.");
+        let failed = WidgetError::CommandFailed {
+            command: String::from("pactl info"),
+            stderr: String::from("Connection failure: Connection refused\npa_context_connect() failed"),
+        };
+        assert_eq!(failed.to_string(), "`pactl info` failed: Connection failure: Connection refused");
+        let ambiguous = WidgetError::AmbiguousDevice {
+            id: String::from("stereo"),
+            candidates: vec![String::from("a"), String::from("b")],
+        };
+        assert_eq!(ambiguous.to_string(), "`stereo` matches a, b.");
+    }
+}
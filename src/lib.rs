@@ -1,11 +1,29 @@
+pub mod audio;
+pub mod block;
+pub mod click;
+pub mod color;
+pub mod command;
+pub mod error;
+pub mod nvidia;
+pub mod pactl;
+pub mod persist;
+pub mod pipewire;
+pub mod pulse;
+pub mod quodlibet;
+pub mod runner;
+
+pub use block::{Align, Block, BlockOpts, Format};
+pub use click::{ClickBinding, ClickEvent};
+pub use color::{Color, Gradient};
+pub use command::{Command, CommandOutput};
+pub use error::WidgetError;
+pub use persist::{Event, Persist};
+pub use runner::{CommandRunner, FixtureRunner, SystemRunner};
+
+/// Trimmed stdout of a command, or None if it couldn't run, timed out, or printed nothing.
+/// Doesn't care about the exit code. Use `Command` directly to know what went wrong.
 pub fn shell(command: &str, args: &[&str]) -> Option<String> {
-    let mut cmd = std::process::Command::new(command);
-    cmd.args(args);
-    let result = match cmd.output() {
-        Ok(result) => Some(String::from_utf8(result.stdout).unwrap().trim().to_string()),
-        Err(_) => None,
-    };
-    return if result == Some("".to_string()) {None} else {result};
+    SystemRunner.shell(command, args)
 }
 
 #[cfg(test)]
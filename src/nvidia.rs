@@ -0,0 +1,688 @@
+use clap::Clap;
+use crate::{Block, BlockOpts, Command, CommandRunner, Gradient, WidgetError};
+use regex::Regex;
+use std::str::FromStr;
+
+/// What `--gradient` colors by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    /// GPU utilization, in percent.
+    Util,
+    /// VRAM used, in percent.
+    Mem,
+    /// Temperature, in C.
+    Temp,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "util" => Ok(Metric::Util),
+            "mem" => Ok(Metric::Mem),
+            "temp" => Ok(Metric::Temp),
+            _ => Err(format!("Unknown metric `{}`, should be util, mem, or temp", s)),
+        }
+    }
+}
+
+/// Why the GPU is running slower than it could, worst first.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Throttle {
+    /// The hardware itself is pulling the clocks down, from overheating or the power supply.
+    HwSlowdown,
+    /// The driver is pulling them down for heat.
+    SwThermal,
+    /// At the power limit.
+    PowerCap,
+    /// Held back to match other GPUs in sync boost.
+    SyncBoost,
+}
+
+impl Throttle {
+    /// Short name for the long text.
+    pub fn name(self) -> &'static str {
+        match self {
+            Throttle::HwSlowdown => "HW SLOWDOWN",
+            Throttle::SwThermal => "THERMAL",
+            Throttle::PowerCap => "POWER CAP",
+            Throttle::SyncBoost => "SYNC BOOST",
+        }
+    }
+
+    /// The active ones, worst first.
+    pub fn from_flags(hw_slowdown: bool, sw_thermal: bool, power_cap: bool, sync_boost: bool) -> Vec<Throttle> {
+        let flags = [
+            (hw_slowdown, Throttle::HwSlowdown),
+            (sw_thermal, Throttle::SwThermal),
+            (power_cap, Throttle::PowerCap),
+            (sync_boost, Throttle::SyncBoost),
+        ];
+        flags.iter().filter(|(active, _)| *active).map(|(_, throttle)| *throttle).collect()
+    }
+}
+
+/// Active reasons out of the `Clocks Throttle Reasons` section of `nvidia-smi -q`, worst first.
+/// Newer drivers call it `Clocks Event Reasons`.
+pub fn throttle_reasons(status: &str) -> Vec<Throttle> {
+    // Everything indented under the section header
+    let section = Regex::new(r"(?m)^ *Clocks (?:Throttle|Event) Reasons *\n((?:^ {6,}[^\n]*\n?)*)")
+        .unwrap()
+        .captures(status)
+        .map(|caps| caps[1].to_string())
+        .unwrap_or_default();
+    let active = |reason: &str| {
+        Regex::new(&format!(r"(?m)^ +{} +: Active *$", reason))
+            .unwrap()
+            .is_match(&section)
+    };
+    // HW Slowdown covers both of the ones under it, but not every driver sets it with them
+    let hw = ["HW Slowdown", "HW Thermal Slowdown", "HW Power Brake Slowdown"].iter().any(|reason| active(reason));
+    Throttle::from_flags(hw, active("SW Thermal Slowdown"), active("SW Power Cap"), active("Sync Boost"))
+}
+
+/// Something `nvidia-status` can ask `nvidia-smi` for. Only the ones being shown get asked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Name,
+    Util,
+    Mem,
+    Temp,
+    Encoder,
+    Decoder,
+    PowerDraw,
+    PowerLimit,
+    Fan,
+    /// Current and max clocks.
+    Clocks,
+    PState,
+    /// Idle and the throttle reasons.
+    Throttle,
+}
+
+impl Field {
+    /// The `--query-gpu` names, in the order they come back.
+    pub fn query(self) -> &'static [&'static str] {
+        match self {
+            Field::Name => &["name"],
+            Field::Util => &["utilization.gpu"],
+            Field::Mem => &["memory.used", "memory.total"],
+            Field::Temp => &["temperature.gpu"],
+            Field::Encoder => &["utilization.encoder"],
+            Field::Decoder => &["utilization.decoder"],
+            Field::PowerDraw => &["power.draw"],
+            Field::PowerLimit => &["power.limit"],
+            Field::Fan => &["fan.speed"],
+            Field::Clocks => {
+                &["clocks.gr", "clocks.sm", "clocks.mem", "clocks.max.gr", "clocks.max.sm", "clocks.max.mem"]
+            }
+            Field::PState => &["pstate"],
+            Field::Throttle => &[
+                "clocks_throttle_reasons.gpu_idle",
+                "clocks_throttle_reasons.hw_slowdown",
+                "clocks_throttle_reasons.hw_thermal_slowdown",
+                "clocks_throttle_reasons.hw_power_brake_slowdown",
+                "clocks_throttle_reasons.sw_thermal_slowdown",
+                "clocks_throttle_reasons.sw_power_cap",
+                "clocks_throttle_reasons.sync_boost",
+            ],
+        }
+    }
+}
+
+/// Clock speeds, in MHz.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Clocks {
+    pub graphics: f32,
+    pub sm: f32,
+    pub memory: f32,
+}
+
+/// What a GPU is up to. Anything that wasn't asked for is left `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GpuStats {
+    /// Without the brand, ex GTX 1070.
+    pub name: Option<String>,
+    /// Percent.
+    pub utilization: Option<f32>,
+    /// Used and total VRAM, in MiB.
+    pub memory: Option<(f32, f32)>,
+    /// C
+    pub temperature: Option<f32>,
+    /// Percent.
+    pub encoder: Option<f32>,
+    /// Percent.
+    pub decoder: Option<f32>,
+    /// W
+    pub power_draw: Option<f32>,
+    /// W, what it's currently limited to.
+    pub power_limit: Option<f32>,
+    /// Percent. GPUs without fans don't have it at all.
+    pub fan: Option<f32>,
+    pub clocks: Option<Clocks>,
+    /// What it boosts up to.
+    pub max_clocks: Option<Clocks>,
+    /// P0 is flat out, down to P12 for idling.
+    pub pstate: Option<String>,
+    pub idle: bool,
+    /// Worst first.
+    pub throttle: Vec<Throttle>,
+}
+
+// Product names are "GeForce GTX 1070" on old drivers and "NVIDIA GeForce GTX 1070" on new ones
+fn model(name: &str) -> String {
+    let brands = ["NVIDIA", "GeForce", "Quadro", "Tesla"];
+    let words: Vec<&str> = name.split_whitespace().skip_while(|word| brands.contains(word)).collect();
+    words.join(" ")
+}
+
+impl GpuStats {
+    /// Asks `nvidia-smi --query-gpu` for `fields` of GPU `id`, or every GPU in order if there's no
+    /// id. Way faster than the whole `-q` dump.
+    pub fn query(id: Option<i32>, fields: &[Field], runner: &dyn CommandRunner) -> Result<Vec<GpuStats>, WidgetError> {
+        let names: Vec<&str> = fields.iter().flat_map(|field| field.query().iter().copied()).collect();
+        let query = format!("--query-gpu={}", names.join(","));
+        let id = id.map(|id| id.to_string());
+        let mut args = vec![query.as_str(), "--format=csv,noheader,nounits"];
+        if let Some(id) = &id {
+            args.extend(&["-i", id.as_str()]);
+        }
+        let csv = runner.run(&Command::new("nvidia-smi").args(&args))?;
+        csv.lines().map(|line| GpuStats::from_csv(line, fields)).collect()
+    }
+
+    /// Same as `query`, out of `nvidia-smi -q` instead.
+    pub fn dump(id: Option<i32>, fields: &[Field], runner: &dyn CommandRunner) -> Result<Vec<GpuStats>, WidgetError> {
+        let id = id.map(|id| id.to_string());
+        let mut args = vec!["-q"];
+        if let Some(id) = &id {
+            args.extend(&["-i", id.as_str()]);
+        }
+        let status = runner.run(&Command::new("nvidia-smi").args(&args))?;
+        // Each GPU starts at its bus id, ex "GPU 00000000:01:00.0"
+        let starts: Vec<usize> = Regex::new(r"(?m)^GPU [[:xdigit:]:.]+$")
+            .unwrap()
+            .find_iter(&status)
+            .map(|found| found.start())
+            .collect();
+        if starts.is_empty() {
+            return Ok(vec![GpuStats::from_status(&status, fields)?]);
+        }
+        let mut ends = starts[1..].to_vec();
+        ends.push(status.len());
+        starts
+            .iter()
+            .zip(ends)
+            .map(|(&start, end)| GpuStats::from_status(&status[start..end], fields))
+            .collect()
+    }
+
+    /// `query`, or `dump` on drivers that turn the query down or answer it with something unreadable.
+    /// A missing or hung `nvidia-smi` won't do any better with `-q`, so those errors come straight back.
+    pub fn get(id: Option<i32>, fields: &[Field], runner: &dyn CommandRunner) -> Result<Vec<GpuStats>, WidgetError> {
+        match GpuStats::query(id, fields, runner) {
+            Err(WidgetError::CommandFailed { .. }) | Err(WidgetError::Parse(_)) => GpuStats::dump(id, fields, runner),
+            result => result,
+        }
+    }
+
+    /// One line of `--query-gpu` output, asked for with `fields`.
+    pub fn from_csv(line: &str, fields: &[Field]) -> Result<GpuStats, WidgetError> {
+        let mut values = line.split(',').map(str::trim);
+        let mut stats = GpuStats::default();
+        for field in fields {
+            match field {
+                Field::Name => stats.name = Some(model(value(&mut values, "GPU name")?)),
+                Field::Util => stats.utilization = Some(number(&mut values, "utilization")?),
+                Field::Mem => stats.memory = Some((number(&mut values, "memory")?, number(&mut values, "memory")?)),
+                Field::Temp => stats.temperature = Some(number(&mut values, "temperature")?),
+                Field::Encoder => stats.encoder = Some(number(&mut values, "encoder utilization")?),
+                Field::Decoder => stats.decoder = Some(number(&mut values, "decoder utilization")?),
+                Field::PowerDraw => stats.power_draw = optional(&mut values, "power draw")?,
+                Field::PowerLimit => stats.power_limit = optional(&mut values, "power limit")?,
+                Field::Fan => stats.fan = optional(&mut values, "fan speed")?,
+                Field::Clocks => {
+                    let mut clocks = || -> Result<Clocks, WidgetError> {
+                        Ok(Clocks {
+                            graphics: number(&mut values, "clocks")?,
+                            sm: number(&mut values, "clocks")?,
+                            memory: number(&mut values, "clocks")?,
+                        })
+                    };
+                    stats.clocks = Some(clocks()?);
+                    stats.max_clocks = Some(clocks()?);
+                }
+                Field::PState => stats.pstate = Some(value(&mut values, "performance state")?.to_string()),
+                // Cards that can't tell say [Not Supported], that's as good as not throttling
+                Field::Throttle => {
+                    let mut active = Vec::new();
+                    for _ in Field::Throttle.query() {
+                        let value = values.next().ok_or_else(|| WidgetError::Parse(String::from("throttle reasons")))?;
+                        active.push(value == "Active");
+                    }
+                    stats.idle = active[0];
+                    stats.throttle = Throttle::from_flags(active[1] || active[2] || active[3], active[4], active[5], active[6]);
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Same as `from_csv`, but out of one GPU's part of `nvidia-smi -q`. For drivers too old to query.
+    pub fn from_status(status: &str, fields: &[Field]) -> Result<GpuStats, WidgetError> {
+        let number = |re: &str, what: &str| -> Result<Option<f32>, WidgetError> {
+            capture(re, status, what)?.parse().map(Some).map_err(|_| WidgetError::Parse(what.to_string()))
+        };
+        // For the ones not every GPU has. Anything but a number on the line, like N/A, or no line at all is None.
+        let optional = |re: &str| -> Option<f32> {
+            let value = Regex::new(re).unwrap().captures(status)?.get(1)?.as_str();
+            value.split_whitespace().next()?.parse().ok()
+        };
+        let mut stats = GpuStats::default();
+        for field in fields {
+            match field {
+                // Since there's no lookaround, I put the important text in a separate capture group.
+                Field::Util => stats.utilization = number(r"Gpu +: ([\d]+)", "utilization")?,
+                Field::Mem => stats.memory = Some(memory(status)?),
+                Field::Temp => stats.temperature = number(r"GPU Current Temp +: ([\d]+)", "temperature")?,
+                Field::Encoder => stats.encoder = number(r"Encoder +: ([\d]+)", "encoder utilization")?,
+                Field::Decoder => stats.decoder = number(r"Decoder +: ([\d]+)", "decoder utilization")?,
+                // Newer drivers have an average and an instantaneous draw, and call the limit the current one.
+                // Anchored to the line start so Default Power Limit and the rest don't count.
+                Field::PowerDraw => {
+                    stats.power_draw = optional(r"\n +(?:Average |Instantaneous )?Power Draw +: ([^\n]+)")
+                }
+                Field::PowerLimit => stats.power_limit = optional(r"\n +(?:Current )?Power Limit +: ([^\n]+)"),
+                Field::Fan => stats.fan = optional(r"Fan Speed +: ([^\n]+)"),
+                Field::Clocks => {
+                    stats.clocks = Some(clocks(status, "Clocks")?);
+                    stats.max_clocks = Some(clocks(status, "Max Clocks")?);
+                }
+                Field::PState => {
+                    stats.pstate = Some(capture(r"Performance State +: (P\d+)", status, "performance state")?.to_string())
+                }
+                // Product name, without the brand name in front.
+                // Ex, product = GeForce GTX 1070; Brand = GeForce; Name = GTX 1070
+                Field::Name => {
+                    let name = capture(r"Product Name +: ([^\n]+)", status, "GPU name")?;
+                    let brand = capture(r"Product Brand +: ([^\n]+)", status, "GPU brand").unwrap_or("");
+                    stats.name = Some(model(&name.replace(brand, "")));
+                }
+                // "Throttle status -- Idle : Active". Some cards only have `Clocks Throttle Reasons : N/A`,
+                // those never count as idle or throttling.
+                Field::Throttle => {
+                    stats.idle = capture(r"Idle +: ([[:alpha:]]+)", status, "idle status") == Ok("Active");
+                    stats.throttle = throttle_reasons(status);
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+// Next --query-gpu value. Unsupported ones come back as [N/A] or [Not Supported], those count as missing.
+fn value<'a>(values: &mut dyn Iterator<Item = &'a str>, what: &str) -> Result<&'a str, WidgetError> {
+    match values.next() {
+        Some(value) if !value.is_empty() && !value.starts_with('[') => Ok(value),
+        _ => Err(WidgetError::Parse(what.to_string())),
+    }
+}
+
+fn number(values: &mut dyn Iterator<Item = &str>, what: &str) -> Result<f32, WidgetError> {
+    value(values, what)?.parse().map_err(|_| WidgetError::Parse(what.to_string()))
+}
+
+// `number` for the ones not every GPU has, like a fan. Those are None instead of an error.
+fn optional(values: &mut dyn Iterator<Item = &str>, what: &str) -> Result<Option<f32>, WidgetError> {
+    match values.next() {
+        Some(value) if value.is_empty() || value.starts_with('[') => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(|_| WidgetError::Parse(what.to_string())),
+        None => Err(WidgetError::Parse(what.to_string())),
+    }
+}
+
+/// First capture group of `re` in `status`, or a parse error naming `what`.
+fn capture<'a>(re: &str, status: &'a str, what: &str) -> Result<&'a str, WidgetError> {
+    // The regexes are all hardcoded, so `new` can't fail.
+    match Regex::new(re).unwrap().captures(status) {
+        Some(caps) => Ok(caps.get(1).unwrap().as_str()),
+        None => Err(WidgetError::Parse(what.to_string())),
+    }
+}
+
+/// The `Clocks` or `Max Clocks` section. There's a bunch of others with the same lines in them.
+fn clocks(status: &str, section: &str) -> Result<Clocks, WidgetError> {
+    let what = section.to_lowercase();
+    let re = format!(r"\n *{} *\n +Graphics +: (\d+) MHz\n +SM +: (\d+) MHz\n +Memory +: (\d+) MHz", section);
+    let caps = Regex::new(&re)
+        .unwrap()
+        .captures(status)
+        .ok_or_else(|| WidgetError::Parse(what.clone()))?;
+    let parse = |i: usize| caps[i].parse::<f32>().map_err(|_| WidgetError::Parse(what.clone()));
+    Ok(Clocks { graphics: parse(1)?, sm: parse(2)?, memory: parse(3)? })
+}
+
+/// Used and total VRAM, in MiB.
+fn memory(status: &str) -> Result<(f32, f32), WidgetError> {
+    // this one grabs two groups, one for Total and one for Used
+    let caps = Regex::new(r"FB Memory Usage[ \n]+Total +: ([\d]+) MiB[ \n]+Used +: ([\d]+) MiB")
+        .unwrap()
+        .captures(status)
+        .ok_or_else(|| WidgetError::Parse(String::from("memory")))?;
+    // converts groups into floats for easy usage % later.
+    let parse = |i: usize| caps.get(i).unwrap().as_str().parse::<f32>();
+    match (parse(1), parse(2)) {
+        (Ok(total), Ok(used)) => Ok((used, total)),
+        _ => Err(WidgetError::Parse(String::from("memory"))),
+    }
+}
+
+/// i3blocks display for NVIDIA GPUs.
+#[derive(Clap)]
+#[clap(author = "Beinsezii")]
+pub struct Opts {
+    /// Numeric id of GPU. Only relevant for multi-gpu systems, and ignored with `--all` or `--summary`.
+    #[clap(long, default_value = "0")]
+    id: i32,
+
+    #[clap(long)]
+    background_idle: Option<String>,
+
+    #[clap(long)]
+    background_throttle: Option<String>,
+
+    #[clap(long, default_value = "#268bd2")]
+    color_idle: String,
+
+    /// Color while throttling, for reasons without a color of their own.
+    #[clap(long, default_value = "#b58900")]
+    color_throttle: String,
+
+    /// Color while the hardware slows itself down. The worst kind.
+    #[clap(long, default_value = "#dc322f")]
+    color_hw_slowdown: String,
+
+    /// Color while the driver slows it down for heat.
+    #[clap(long, default_value = "#cb4b16")]
+    color_thermal: String,
+
+    /// Color while at the power limit. `--color-throttle` otherwise.
+    #[clap(long)]
+    color_power_cap: Option<String>,
+
+    /// Color while held back by sync boost. `--color-throttle` otherwise.
+    #[clap(long)]
+    color_sync_boost: Option<String>,
+
+    /// Names the worst throttle reason in long, if there is one.
+    #[clap(long)]
+    throttle_reason: bool,
+
+    /// Shows every GPU instead of just `--id`, ex `0:45% 1:98%`. Long adds memory % and temperature.
+    #[clap(long)]
+    all: bool,
+
+    /// Sums up every GPU instead: mean utilization, total memory and the hottest temperature.
+    #[clap(long, conflicts_with = "all")]
+    summary: bool,
+
+    /// Color by `--gradient-metric`, ex `0:#268bd2,60:#b58900,85:#dc322f`. Stops are value:color,
+    /// anything in between gets mixed. Idle and throttle colors still win.
+    #[clap(long)]
+    gradient: Option<Gradient>,
+
+    /// What `--gradient` goes by. util, mem, or temp. Doesn't have to be shown.
+    #[clap(long, default_value = "util")]
+    gradient_metric: Metric,
+
+    /// Disables showing utilization.
+    #[clap(long)]
+    no_util: bool,
+
+    /// Disables showing memory.
+    #[clap(long)]
+    no_mem: bool,
+
+    /// Disables showing temperature.
+    #[clap(long)]
+    no_temp: bool,
+
+    /// Always shows memory as a % instead of MiB
+    #[clap(long)]
+    perc_mem: bool,
+
+    /// Enables showing temperature in short as well as long.
+    #[clap(long)]
+    short_temp: bool,
+
+    /// Shows GPU name in long.
+    #[clap(long, short = "g")]
+    gpu_name: bool,
+
+    /// Shows video encoder utilization in long.
+    #[clap(long, short = "e")]
+    encode: bool,
+
+    /// Shows video decoder utilization in long.
+    #[clap(long, short = "d")]
+    decode: bool,
+
+    /// Shows how many watts it's drawing, in long and short.
+    #[clap(long)]
+    power_draw: bool,
+
+    /// Shows the power limit, in long and short. Goes after the draw if that's shown too, ex 61/151W.
+    #[clap(long)]
+    power_limit: bool,
+
+    /// Shows fan speed, in long and short.
+    #[clap(long)]
+    fan: bool,
+
+    /// Shows graphics, SM and memory clocks against their max in long, ex GFX 1708/1911MHz.
+    #[clap(long)]
+    clocks: bool,
+
+    /// Shows the performance state in long and short, P0 being flat out.
+    #[clap(long)]
+    pstate: bool,
+
+    #[clap(flatten)]
+    pub block: BlockOpts,
+}
+
+/// Colors `block` for idle, throttling, or the gradient at `value`, in that order.
+fn paint(opts: &Opts, block: &mut Block, idle: bool, throttle: Option<Throttle>, value: f32) {
+    if let Some(gradient) = &opts.gradient {
+        block.color = Some(gradient.color(value));
+    }
+
+    if idle && !opts.color_idle.is_empty() {
+        block.color = Some(opts.color_idle.clone());
+        block.background = opts.background_idle.clone();
+    } else if let Some(throttle) = throttle.filter(|&throttle| !opts.throttle_color(throttle).is_empty()) {
+        block.color = Some(opts.throttle_color(throttle));
+        block.background = opts.background_throttle.clone();
+    };
+}
+
+impl Opts {
+    fn throttle_color(&self, throttle: Throttle) -> String {
+        match throttle {
+            Throttle::HwSlowdown => self.color_hw_slowdown.clone(),
+            Throttle::SwThermal => self.color_thermal.clone(),
+            Throttle::PowerCap => self.color_power_cap.clone().unwrap_or_else(|| self.color_throttle.clone()),
+            Throttle::SyncBoost => self.color_sync_boost.clone().unwrap_or_else(|| self.color_throttle.clone()),
+        }
+    }
+
+    // Idle and throttling only matter if something shows them. Blanking every color turns them off,
+    // like the idle color always could.
+    fn wants_throttle(&self) -> bool {
+        let throttles = [Throttle::HwSlowdown, Throttle::SwThermal, Throttle::PowerCap, Throttle::SyncBoost];
+        !self.color_idle.is_empty()
+            || self.throttle_reason
+            || throttles.iter().any(|&throttle| !self.throttle_color(throttle).is_empty())
+    }
+}
+
+impl GpuStats {
+    fn mem_perc(&self) -> f32 {
+        let (used, total) = self.memory.unwrap_or((0.0, 1.0));
+        used / total * 100.0
+    }
+
+    /// The value `--gradient` goes by.
+    fn metric(&self, metric: Metric) -> f32 {
+        match metric {
+            Metric::Util => self.utilization.unwrap_or_default(),
+            Metric::Temp => self.temperature.unwrap_or_default(),
+            Metric::Mem => self.mem_perc(),
+        }
+    }
+}
+
+pub fn run(opts: &Opts, runner: &dyn CommandRunner) -> Result<Block, WidgetError> {
+    let all = opts.all || opts.summary;
+    // Only ask for what's getting shown, plus whatever the gradient goes by even if it's hidden
+    let colored_by = |metric: Metric| opts.gradient.is_some() && opts.gradient_metric == metric;
+    let mut fields = Vec::new();
+    if opts.gpu_name && !all { fields.push(Field::Name) };
+    if !opts.no_util || colored_by(Metric::Util) { fields.push(Field::Util) };
+    if !opts.no_mem || colored_by(Metric::Mem) { fields.push(Field::Mem) };
+    if !opts.no_temp || colored_by(Metric::Temp) { fields.push(Field::Temp) };
+    if opts.encode && !all { fields.push(Field::Encoder) };
+    if opts.decode && !all { fields.push(Field::Decoder) };
+    if opts.power_draw && !opts.all { fields.push(Field::PowerDraw) };
+    if opts.power_limit && !opts.all { fields.push(Field::PowerLimit) };
+    if opts.fan && !opts.all { fields.push(Field::Fan) };
+    if opts.clocks && !all { fields.push(Field::Clocks) };
+    if opts.pstate && !all { fields.push(Field::PState) };
+    if opts.wants_throttle() { fields.push(Field::Throttle) };
+
+    // --query-gpu is quick, but older drivers don't know every field. The full `nvidia-smi -q`
+    // dump has literally all the information, so it's the fallback.
+    if all {
+        let gpus = GpuStats::get(None, &fields, runner)?;
+        if gpus.is_empty() {
+            return Err(WidgetError::DeviceNotFound(String::from("GPUs")));
+        }
+        return Ok(if opts.summary { summary(opts, &gpus) } else { every(opts, &gpus) });
+    }
+    let stats = GpuStats::get(Some(opts.id), &fields, runner)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| WidgetError::DeviceNotFound(opts.id.to_string()))?;
+    let utilization = stats.utilization.unwrap_or_default();
+    let (memory, max_memory) = stats.memory.unwrap_or((0.0, 1.0));
+    let temperature = stats.temperature.unwrap_or_default();
+    let throttle = stats.throttle.first().copied();
+
+    // is there a way to make RustFMT ignore a part? Everything below here looks better manually
+    // formatted imo.
+    let mut long = match &stats.name { Some(name) => format!("{}: ", name),
+                                       None => format!("GPU {}: ", opts.id) };
+    if !opts.no_util { long += &format!("GPU {}%|", utilization) };
+    if !opts.no_mem && !opts.perc_mem { long += &format!("{}/{}MiB|", memory, max_memory) };
+    if !opts.no_mem && opts.perc_mem { long += &format!("MEM {:.0}%|", stats.mem_perc()) };
+    if !opts.no_temp { long += &format!("{}C|", temperature) };
+    if let Some(encode) = stats.encoder { long += &format!("ENC {}%|", encode) };
+    if let Some(decode) = stats.decoder { long += &format!("VID {}%|", decode) };
+    if let Some(power) = power(stats.power_draw, stats.power_limit) { long += &format!("{}|", power) };
+    if let Some(fan) = stats.fan { long += &format!("FAN {}%|", fan) };
+    if let (Some(now), Some(max)) = (stats.clocks, stats.max_clocks) {
+        long += &format!("GFX {}/{}MHz|", now.graphics, max.graphics);
+        long += &format!("SM {}/{}MHz|", now.sm, max.sm);
+        long += &format!("VRAM {}/{}MHz|", now.memory, max.memory);
+    };
+    if let Some(pstate) = &stats.pstate { long += &format!("{}|", pstate) };
+    if opts.throttle_reason { if let Some(throttle) = throttle { long += throttle.name() } };
+    let mut block = Block::new(long.trim_end_matches('|'));
+
+    let mut short = format!("GPU {}: ", opts.id);
+    if !opts.no_util { short += &format!("GPU {}|", utilization) };
+    if !opts.no_mem { short += &format!("MEM {:.0}|", stats.mem_perc()) };
+    if opts.short_temp && !opts.no_temp { short += &format!("{}C|", temperature) };
+    if let Some(power) = power(stats.power_draw, stats.power_limit) { short += &format!("{}|", power) };
+    if let Some(fan) = stats.fan { short += &format!("FAN {}|", fan) };
+    if let Some(pstate) = &stats.pstate { short += pstate };
+    block.short_text = Some(short.trim_end_matches('|').to_string());
+
+    paint(opts, &mut block, stats.idle, throttle, stats.metric(opts.gradient_metric));
+    Ok(block)
+}
+
+/// Watts, ex `61/151W` for a draw and limit.
+fn power(draw: Option<f32>, limit: Option<f32>) -> Option<String> {
+    match (draw, limit) {
+        (Some(draw), Some(limit)) => Some(format!("{:.0}/{:.0}W", draw, limit)),
+        (Some(draw), None) => Some(format!("{:.0}W", draw)),
+        (None, Some(limit)) => Some(format!("LIM {:.0}W", limit)),
+        (None, None) => None,
+    }
+}
+
+// Colors for the worst of them: idle only if they all are, the worst throttle reason on any, and
+// the gradient by whichever's highest.
+fn paint_worst(opts: &Opts, block: &mut Block, gpus: &[GpuStats]) {
+    let idle = gpus.iter().all(|gpu| gpu.idle);
+    let throttle = gpus.iter().filter_map(|gpu| gpu.throttle.first().copied()).min();
+    let value = gpus.iter().map(|gpu| gpu.metric(opts.gradient_metric)).fold(f32::MIN, f32::max);
+    paint(opts, block, idle, throttle, value);
+    if opts.throttle_reason {
+        if let Some(throttle) = throttle {
+            block.full_text = format!("{} {}", block.full_text, throttle.name());
+        }
+    }
+}
+
+/// `--all`, one compact entry per GPU.
+fn every(opts: &Opts, gpus: &[GpuStats]) -> Block {
+    let mut long = Vec::new();
+    let mut short = Vec::new();
+    for (id, gpu) in gpus.iter().enumerate() {
+        let mut parts = Vec::new();
+        if !opts.no_util { parts.push(format!("{}%", gpu.utilization.unwrap_or_default())) };
+        if !opts.no_mem { parts.push(format!("{:.0}%", gpu.mem_perc())) };
+        if !opts.no_temp { parts.push(format!("{}C", gpu.temperature.unwrap_or_default())) };
+        long.push(format!("{}:{}", id, parts.join("/")));
+        // Short only keeps the first thing shown, util normally
+        short.push(format!("{}:{}", id, parts.first().cloned().unwrap_or_default()));
+    }
+    let mut block = Block::new(&long.join(" "));
+    block.short_text = Some(short.join(" "));
+    paint_worst(opts, &mut block, gpus);
+    block
+}
+
+/// `--summary`, every GPU rolled into one.
+fn summary(opts: &Opts, gpus: &[GpuStats]) -> Block {
+    let utilization = gpus.iter().map(|gpu| gpu.utilization.unwrap_or_default()).sum::<f32>() / gpus.len() as f32;
+    let memory = gpus.iter().map(|gpu| gpu.memory.unwrap_or_default().0).sum::<f32>();
+    let max_memory = gpus.iter().map(|gpu| gpu.memory.unwrap_or_default().1).sum::<f32>();
+    let temperature = gpus.iter().map(|gpu| gpu.temperature.unwrap_or_default()).fold(0.0, f32::max);
+    // Whole box's power, and the loudest fan
+    let total = |power: fn(&GpuStats) -> Option<f32>| gpus.iter().map(power).sum::<Option<f32>>();
+    let power = power(total(|gpu| gpu.power_draw), total(|gpu| gpu.power_limit));
+    let fan = gpus.iter().filter_map(|gpu| gpu.fan).reduce(f32::max);
+
+    let mut long = format!("{} GPUs: ", gpus.len());
+    if !opts.no_util { long += &format!("GPU {:.0}%|", utilization) };
+    if !opts.no_mem && !opts.perc_mem { long += &format!("{}/{}MiB|", memory, max_memory) };
+    if !opts.no_mem && opts.perc_mem { long += &format!("MEM {:.0}%|", memory / max_memory * 100.0) };
+    if !opts.no_temp { long += &format!("{}C|", temperature) };
+    if let Some(power) = &power { long += &format!("{}|", power) };
+    if let Some(fan) = fan { long += &format!("FAN {}%|", fan) };
+    let mut block = Block::new(long.trim_end_matches('|'));
+
+    let mut short = format!("{} GPUs: ", gpus.len());
+    if !opts.no_util { short += &format!("GPU {:.0}|", utilization) };
+    if !opts.no_mem { short += &format!("MEM {:.0}|", memory / max_memory * 100.0) };
+    if opts.short_temp && !opts.no_temp { short += &format!("{}C|", temperature) };
+    if let Some(power) = &power { short += &format!("{}|", power) };
+    if let Some(fan) = fan { short += &format!("FAN {}", fan) };
+    block.short_text = Some(short.trim_end_matches('|').to_string());
+
+    paint_worst(opts, &mut block, gpus);
+    block
+}
@@ -0,0 +1,441 @@
+use crate::audio::{Backend, Card, ChannelVolume, Device, Port, Profile, Target};
+use crate::{Command, CommandRunner, WidgetError};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// `pactl` in the C locale. Its text output is translated, so every regex here would miss on a
+/// non-English system otherwise.
+pub fn pactl(args: &[&str]) -> Command {
+    Command::new("pactl").env("LC_ALL", "C").args(args)
+}
+
+#[derive(Deserialize)]
+struct JsonVolume {
+    value_percent: String,
+    db: String,
+}
+
+#[derive(Deserialize)]
+struct JsonPort {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default, rename = "type")]
+    port_type: String,
+    #[serde(default)]
+    availability: String,
+}
+
+#[derive(Deserialize)]
+struct JsonDevice {
+    index: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    channel_map: String,
+    mute: bool,
+    volume: HashMap<String, JsonVolume>,
+    #[serde(default)]
+    ports: Vec<JsonPort>,
+    #[serde(default)]
+    active_port: Option<String>,
+    #[serde(default)]
+    monitor_of_sink: Option<String>,
+    #[serde(default)]
+    sink: Option<u32>,
+    #[serde(default)]
+    source: Option<u32>,
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct JsonProfile {
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    priority: u32,
+    #[serde(default = "available")]
+    available: bool,
+}
+
+fn available() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct JsonCard {
+    index: u32,
+    name: String,
+    #[serde(default)]
+    profiles: HashMap<String, JsonProfile>,
+    #[serde(default)]
+    active_profile: Option<String>,
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct JsonInfo {
+    default_sink_name: String,
+    default_source_name: String,
+}
+
+// pactl uses "n/a" or nothing at all for unset names
+fn not_empty(name: Option<String>) -> Option<String> {
+    name.filter(|name| !name.is_empty() && name != "n/a")
+}
+
+// Streams only have the app's name
+fn stream_name(properties: &HashMap<String, String>) -> Option<String> {
+    not_empty(properties.get("application.name").or_else(|| properties.get("media.name")).cloned())
+}
+
+/// Reads `pactl --format=json list sinks|sources`. None if it isn't a device list.
+pub fn parse_json_devices(json: &str) -> Option<Vec<Device>> {
+    let devices: Vec<JsonDevice> = serde_json::from_str(json).ok()?;
+    Some(
+        devices
+            .into_iter()
+            .map(|dev| {
+                // JSON objects don't keep order, the channel map does
+                let mut volume: Vec<ChannelVolume> = dev
+                    .volume
+                    .iter()
+                    .map(|(channel, vol)| ChannelVolume {
+                        channel: channel.clone(),
+                        percent: vol.value_percent.trim_end_matches('%').trim().parse().unwrap_or(0),
+                        db: vol.db.trim().to_string(),
+                    })
+                    .collect();
+                let order: Vec<&str> = dev.channel_map.split(',').collect();
+                volume.sort_by_key(|vol| order.iter().position(|ch| *ch == vol.channel).unwrap_or(usize::MAX));
+                let name = match not_empty(Some(dev.name)) {
+                    Some(name) => name,
+                    None => stream_name(&dev.properties).unwrap_or_default(),
+                };
+                Device {
+                    index: dev.index,
+                    name,
+                    description: dev.description,
+                    mute: dev.mute,
+                    volume,
+                    ports: dev
+                        .ports
+                        .into_iter()
+                        .map(|port| Port {
+                            name: port.name,
+                            description: port.description,
+                            port_type: port.port_type,
+                            available: port.availability != "not available",
+                        })
+                        .collect(),
+                    active_port: not_empty(dev.active_port),
+                    monitor_of: not_empty(dev.monitor_of_sink),
+                    connected_to: dev.sink.or(dev.source),
+                    properties: dev.properties,
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Reads `pactl --format=json list cards`. None if it isn't a card list.
+pub fn parse_json_cards(json: &str) -> Option<Vec<Card>> {
+    let cards: Vec<JsonCard> = serde_json::from_str(json).ok()?;
+    Some(
+        cards
+            .into_iter()
+            .map(|card| {
+                // Profiles are an object here, put them back in pactl's order
+                let mut profiles: Vec<(String, JsonProfile)> = card.profiles.into_iter().collect();
+                profiles.sort_by(|a, b| b.1.priority.cmp(&a.1.priority).then_with(|| a.0.cmp(&b.0)));
+                Card {
+                    index: card.index,
+                    name: card.name,
+                    profiles: profiles
+                        .into_iter()
+                        .enumerate()
+                        .map(|(n, (name, profile))| Profile {
+                            index: n as u32,
+                            name,
+                            description: profile.description,
+                            available: profile.available,
+                        })
+                        .collect(),
+                    active_profile: not_empty(card.active_profile),
+                    properties: card.properties,
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Reads the text `pactl list cards`.
+pub fn parse_cards(list: &str) -> Vec<Card> {
+    let header = Regex::new(r"(?m)^Card #(\d+)[ \t]*$").unwrap();
+    let field = |text: &str, name: &str| {
+        Regex::new(&format!(r"(?m)^\t{}: ([^\n]*)$", name))
+            .unwrap()
+            .captures(text)
+            .map(|caps| caps[1].trim().to_string())
+    };
+    // Ports have a priority in their parens too, only profiles say how many sinks they make
+    let profile = Regex::new(r"(?m)^\t\t(\S+): ([^\n]*) \((sinks: [^()\n]*)\)$").unwrap();
+    let property = Regex::new(r#"(?m)^\t\t(\S+) = "([^\n]*)"$"#).unwrap();
+
+    let starts: Vec<_> = header.captures_iter(list).map(|caps| caps.get(0).unwrap().start()).collect();
+    let mut cards = Vec::new();
+    for (n, start) in starts.iter().enumerate() {
+        let text = &list[*start..*starts.get(n + 1).unwrap_or(&list.len())];
+        let index = header.captures(text).and_then(|caps| caps[1].parse().ok());
+        let (index, name) = match (index, field(text, "Name")) {
+            (Some(index), Some(name)) => (index, name),
+            _ => continue,
+        };
+        // Port properties are indented further, so these are only the card's
+        let properties = property
+            .captures_iter(text)
+            .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+            .collect();
+        cards.push(Card {
+            index,
+            name,
+            profiles: profile
+                .captures_iter(text)
+                .enumerate()
+                .map(|(n, caps)| Profile {
+                    index: n as u32,
+                    name: caps[1].to_string(),
+                    description: caps[2].to_string(),
+                    available: !caps[3].ends_with("available: no"),
+                })
+                .collect(),
+            active_profile: not_empty(field(text, "Active Profile")),
+            properties,
+        });
+    }
+    cards
+}
+
+/// Reads the text `pactl list sinks|sources|sink-inputs|source-outputs`, for pactl older than 16
+/// which has no JSON. Sections without a name are skipped.
+pub fn parse_devices(list: &str) -> Vec<Device> {
+    let header = Regex::new(r"(?m)^\S[^\n]*#(\d+)[ \t]*$").unwrap();
+    let field = |text: &str, name: &str| {
+        Regex::new(&format!(r"(?m)^\s+{}: ([^\n]*)$", name))
+            .unwrap()
+            .captures(text)
+            .map(|caps| caps[1].trim().to_string())
+    };
+    let channel = Regex::new(r"([\w-]+): \d+ / *(\d+)% / *([^,]+?dB)").unwrap();
+    // The (...) at the end always has the priority, the description before it might have parens too
+    let port = Regex::new(r"(?m)^\t\t(\S+): ([^\n]*) \(([^()\n]*priority[^()\n]*)\)$").unwrap();
+    let port_type = Regex::new(r"type: (\w+)").unwrap();
+    let property = Regex::new(r#"(?m)^\t\t(\S+) = "([^\n]*)"$"#).unwrap();
+
+    let starts: Vec<_> = header.captures_iter(list).map(|caps| caps.get(0).unwrap().start()).collect();
+    let mut devices = Vec::new();
+    for (n, start) in starts.iter().enumerate() {
+        let text = &list[*start..*starts.get(n + 1).unwrap_or(&list.len())];
+        let index = header.captures(text).and_then(|caps| caps[1].parse().ok());
+        let properties: HashMap<String, String> = property
+            .captures_iter(text)
+            .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+            .collect();
+        let (index, name) = match (index, field(text, "Name").or_else(|| stream_name(&properties))) {
+            (Some(index), Some(name)) => (index, name),
+            _ => continue,
+        };
+        devices.push(Device {
+            index,
+            name,
+            description: field(text, "Description").unwrap_or_default(),
+            mute: field(text, "Mute").as_deref() == Some("yes"),
+            volume: field(text, "Volume")
+                .map(|line| {
+                    channel
+                        .captures_iter(&line)
+                        .map(|caps| ChannelVolume {
+                            channel: caps[1].to_string(),
+                            percent: caps[2].parse().unwrap_or(0),
+                            db: caps[3].trim().to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ports: port
+                .captures_iter(text)
+                .map(|caps| Port {
+                    name: caps[1].to_string(),
+                    description: caps[2].to_string(),
+                    port_type: port_type
+                        .captures(&caps[3])
+                        .map(|t| t[1].to_string())
+                        .unwrap_or_default(),
+                    available: !caps[3].ends_with("not available"),
+                })
+                .collect(),
+            active_port: not_empty(field(text, "Active Port")),
+            monitor_of: not_empty(field(text, "Monitor of Sink")),
+            connected_to: field(text, "Sink")
+                .or_else(|| field(text, "Source"))
+                .and_then(|index| index.parse().ok()),
+            properties,
+        });
+    }
+    devices
+}
+
+/// The pulse backend, through `pactl`.
+pub struct Pactl<'a> {
+    target: Target,
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> Pactl<'a> {
+    pub fn new(target: Target, runner: &'a dyn CommandRunner) -> Pactl<'a> {
+        Pactl { target, runner }
+    }
+
+    fn kind(&self) -> &'static str {
+        self.target.pulse_name()
+    }
+
+    /// What pactl commands take to mean `dev`. Names are steadier, but streams don't have any.
+    fn id(&self, dev: &Device) -> String {
+        if self.target.is_stream() {
+            dev.index.to_string()
+        } else {
+            dev.name.clone()
+        }
+    }
+}
+
+impl Backend for Pactl<'_> {
+    /// Uses JSON when pactl has it, the text list otherwise.
+    fn devices(&self) -> Result<Vec<Device>, WidgetError> {
+        let list = format!("{}s", self.kind());
+        if let Ok(json) = self.runner.run(&pactl(&["--format=json", "list", &list])) {
+            if let Some(devices) = parse_json_devices(&json) {
+                return Ok(devices);
+            }
+        }
+        Ok(parse_devices(&self.runner.run(&pactl(&["list", &list]))?))
+    }
+
+    fn streams(&self) -> Result<Vec<Device>, WidgetError> {
+        Pactl::new(Target::new(self.target.is_source(), true), self.runner).devices()
+    }
+
+    fn default_device(&self) -> Result<String, WidgetError> {
+        if let Ok(json) = self.runner.run(&pactl(&["--format=json", "info"])) {
+            if let Ok(info) = serde_json::from_str::<JsonInfo>(&json) {
+                return Ok(if self.target.is_source() { info.default_source_name } else { info.default_sink_name });
+            }
+        }
+        let what = if self.target.is_source() { "Default Source" } else { "Default Sink" };
+        let info = self.runner.run(&pactl(&["info"]))?;
+        Regex::new(&format!(r"(?m)^{}: ([^\n]+)$", what))
+            .unwrap()
+            .captures(&info)
+            .map(|caps| caps[1].trim().to_string())
+            .ok_or_else(|| WidgetError::Parse(what.to_lowercase()))
+    }
+
+    fn toggle_mute(&self, dev: &Device) -> Result<(), WidgetError> {
+        let cmd = format!("set-{}-mute", self.kind());
+        self.runner.output(&pactl(&[&cmd, &self.id(dev), "toggle"])).map(|_| ())
+    }
+
+    fn set_port(&self, dev: &Device, port: &Port) -> Result<(), WidgetError> {
+        let cmd = format!("set-{}-port", self.kind());
+        self.runner.output(&pactl(&[&cmd, &self.id(dev), &port.name])).map(|_| ())
+    }
+
+    fn cards(&self) -> Result<Vec<Card>, WidgetError> {
+        if let Ok(json) = self.runner.run(&pactl(&["--format=json", "list", "cards"])) {
+            if let Some(cards) = parse_json_cards(&json) {
+                return Ok(cards);
+            }
+        }
+        Ok(parse_cards(&self.runner.run(&pactl(&["list", "cards"]))?))
+    }
+
+    fn set_profile(&self, card: &Card, profile: &Profile) -> Result<(), WidgetError> {
+        self.runner.output(&pactl(&["set-card-profile", &card.name, &profile.name])).map(|_| ())
+    }
+
+    fn set_volume(&self, dev: &Device, percent: f64) -> Result<(), WidgetError> {
+        self.set_channels(dev, &[percent])
+    }
+
+    /// pactl takes one volume for every channel, or one per channel.
+    fn set_channels(&self, dev: &Device, percents: &[f64]) -> Result<(), WidgetError> {
+        let mut percents: Vec<String> =
+            percents.iter().map(|percent| format!("{}%", (percent * 100.0).round() / 100.0)).collect();
+        // The same for all of them reads better as one
+        if percents.iter().all(|percent| *percent == percents[0]) {
+            percents.truncate(1);
+        }
+        let cmd = format!("set-{}-volume", self.kind());
+        let id = self.id(dev);
+        let mut args = vec![cmd.as_str(), id.as_str()];
+        args.extend(percents.iter().map(String::as_str));
+        self.runner.output(&pactl(&args)).map(|_| ())
+    }
+
+    fn make_default(&self, dev: &Device) -> Result<(), WidgetError> {
+        let cmd = format!("set-default-{}", self.kind());
+        self.runner.output(&pactl(&[&cmd, &dev.name]))?;
+
+        // Streams recording a monitor are recording a sink's output on purpose, leave them be.
+        let mut monitors = Vec::new();
+        let (list, cmd) = if self.target.is_source() {
+            monitors = self.devices()?.iter().filter(|dev| dev.is_monitor()).map(|dev| dev.index.to_string()).collect();
+            ("source-outputs", "move-source-output")
+        } else {
+            ("sink-inputs", "move-sink-input")
+        };
+        // Nothing playing is an empty list, not an error
+        let streams = self.runner.run(&pactl(&["list", "short", list])).unwrap_or_default();
+        for stream in streams.lines() {
+            let mut columns = stream.split_whitespace();
+            if let (Some(id), Some(device)) = (columns.next(), columns.next()) {
+                if !monitors.iter().any(|monitor| monitor == device) {
+                    let _ = self.runner.output(&pactl(&[cmd, id, &dev.name]));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod pactl_tests {
+    use super::*;
+    #[test]
+    fn c_locale() {
+        let cmd = pactl(&["list", "sinks"]);
+        assert_eq!(cmd.to_string(), "pactl list sinks");
+        assert!(cmd.env.contains(&(String::from("LC_ALL"), String::from("C"))));
+    }
+
+    #[test]
+    fn ports_with_parens() {
+        let list = "Sink #3\n\tName: spdif\n\tMute: no\n\tPorts:\n\t\tiec958-stereo-output: Digital Output (S/PDIF) (type: SPDIF, priority: 0, not available)\n";
+        let devices = parse_devices(list);
+        assert_eq!(
+            devices[0].ports,
+            vec![Port {
+                name: String::from("iec958-stereo-output"),
+                description: String::from("Digital Output (S/PDIF)"),
+                port_type: String::from("SPDIF"),
+                available: false,
+            }]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use crate::block::{Block, BlockOpts};
+use crate::click::ClickEvent;
+use crate::error::WidgetError;
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Things that wake up a persistent widget.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Click(ClickEvent),
+    /// Something outside changed, ex a `pactl subscribe` line. Redraw now instead of at the next
+    /// poll.
+    Refresh,
+    /// Stop and return from `Persist::run`.
+    Quit,
+}
+
+/// Keeps a widget running for i3blocks' `interval=persist`. The widget gets redrawn every poll,
+/// on every click read from stdin, and whenever something sends an `Event` through `sender()`.
+/// A new block is only printed when it actually changed.
+pub struct Persist {
+    tx: Sender<Event>,
+    rx: Receiver<Event>,
+    opts: BlockOpts,
+    poll: Option<Duration>,
+}
+
+impl Persist {
+    /// Also starts reading click events from stdin.
+    pub fn new(opts: &BlockOpts) -> Persist {
+        let persist = Persist::without_stdin(opts);
+        let tx = persist.sender();
+        thread::spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if let Some(click) = ClickEvent::from_line(&line) {
+                    if tx.send(Event::Click(click)).is_err() {
+                        break;
+                    }
+                }
+            }
+            // Stdin closing just means no more clicks, polling keeps going.
+        });
+        persist
+    }
+
+    fn without_stdin(opts: &BlockOpts) -> Persist {
+        let (tx, rx) = channel();
+        Persist {
+            tx,
+            rx,
+            opts: opts.clone(),
+            poll: Some(Duration::from_secs_f32(opts.poll.max(0.01))),
+        }
+    }
+
+    /// Only redraw on clicks and events. For widgets that get told about every change anyway.
+    pub fn without_polling(mut self) -> Persist {
+        self.poll = None;
+        self
+    }
+
+    /// For widgets with their own event sources to wake the loop up.
+    pub fn sender(&self) -> Sender<Event> {
+        self.tx.clone()
+    }
+
+    /// Runs until stdout closes or an `Event::Quit` comes in. `update` gets the click that
+    /// triggered the redraw, if there was one.
+    pub fn run<F>(self, update: F)
+    where
+        F: FnMut(Option<ClickEvent>) -> Result<Block, WidgetError>,
+    {
+        // Not locked for the whole loop, a panic on another thread still has to print its error
+        self.run_to(std::io::stdout(), update)
+    }
+
+    fn run_to<W, F>(self, mut out: W, mut update: F)
+    where
+        W: Write,
+        F: FnMut(Option<ClickEvent>) -> Result<Block, WidgetError>,
+    {
+        let mut last = None;
+        let mut click = None;
+        loop {
+            let mut block = match update(click.take()) {
+                Ok(block) => block,
+                Err(err) => self.opts.error_block(&err.to_string()),
+            };
+            self.opts.apply(&mut block);
+            let text = block.render_persistent(self.opts.format);
+            if last.as_ref() != Some(&text) {
+                // i3blocks is gone if stdout is, so there's nobody left to draw for.
+                if writeln!(out, "{}", text).and_then(|_| out.flush()).is_err() {
+                    return;
+                }
+                last = Some(text);
+            }
+
+            let event = match self.poll {
+                Some(poll) => self.rx.recv_timeout(poll),
+                None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match event {
+                Ok(Event::Click(event)) => click = Some(event),
+                Ok(Event::Refresh) | Err(RecvTimeoutError::Timeout) => (),
+                Ok(Event::Quit) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod persist_tests {
+    use super::*;
+    use clap::Clap;
+
+    fn persist(args: &[&str]) -> Persist {
+        let opts = BlockOpts::parse_from(std::iter::once(&"widget").chain(args));
+        Persist::without_stdin(&opts)
+    }
+
+    #[test]
+    fn prints_changes_only() {
+        let persist = persist(&[]);
+        let tx = persist.sender();
+        for event in [Event::Refresh, Event::Click(ClickEvent::button(1)), Event::Refresh, Event::Quit] {
+            tx.send(event).unwrap();
+        }
+        let mut out = Vec::new();
+        let mut volume = 50;
+        let mut clicks = Vec::new();
+        persist.run_to(&mut out, |click| {
+            if let Some(click) = click {
+                clicks.push(click.button);
+                volume += 5;
+            }
+            Ok(Block::new(&format!("{}%", volume)))
+        });
+        assert_eq!(String::from_utf8(out).unwrap(), "50%\n55%\n");
+        assert_eq!(clicks, vec![1]);
+    }
+
+    #[test]
+    fn json_and_errors() {
+        let persist = persist(&["--format", "json"]);
+        persist.sender().send(Event::Quit).unwrap();
+        let mut out = Vec::new();
+        persist.run_to(&mut out, |_| Err(WidgetError::Parse(String::from("volume"))));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"full_text\":\"Couldn't find volume.\",\"color\":\"#dc322f\",\"urgent\":true}\n"
+        );
+    }
+
+    #[test]
+    fn no_polling() {
+        let persist = persist(&["--poll", "0.01"]).without_polling();
+        let tx = persist.sender();
+        let waker = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            tx.send(Event::Refresh).unwrap();
+            tx.send(Event::Quit).unwrap();
+        });
+        let mut out = Vec::new();
+        let mut count = 0;
+        persist.run_to(&mut out, |_| {
+            count += 1;
+            Ok(Block::new(&count.to_string()))
+        });
+        waker.join().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n2\n");
+    }
+
+    #[test]
+    fn polls() {
+        let persist = persist(&["--poll", "0.01"]);
+        let tx = persist.sender();
+        let mut out = Vec::new();
+        let mut count = 0;
+        persist.run_to(&mut out, |_| {
+            count += 1;
+            if count == 3 {
+                tx.send(Event::Quit).unwrap();
+            }
+            Ok(Block::new(&count.to_string()))
+        });
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n2\n3\n");
+    }
+}
@@ -0,0 +1,379 @@
+use crate::audio::{Backend, Card, ChannelVolume, Device, Port, Profile, Target};
+use crate::{Command, CommandRunner, WidgetError};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// PipeWire's short channel names to pulse's, so both backends read the same.
+fn channel_name(position: &str) -> String {
+    match position {
+        "MONO" => "mono",
+        "FL" => "front-left",
+        "FR" => "front-right",
+        "FC" => "front-center",
+        "LFE" => "lfe",
+        "RL" => "rear-left",
+        "RR" => "rear-right",
+        "RC" => "rear-center",
+        "SL" => "side-left",
+        "SR" => "side-right",
+        other => return other.to_lowercase(),
+    }
+    .to_string()
+}
+
+// Everything as strings, like pactl has them
+fn properties(props: &Value) -> HashMap<String, String> {
+    props
+        .as_object()
+        .map(|props| {
+            props
+                .iter()
+                .map(|(key, val)| match val {
+                    Value::String(val) => (key.clone(), val.clone()),
+                    val => (key.clone(), val.to_string()),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn is_class(node: &Value, target: Target) -> bool {
+    let class = node["info"]["props"]["media.class"].as_str().unwrap_or_default();
+    match target {
+        Target::Sinks => class == "Audio/Sink",
+        Target::Sources => class.starts_with("Audio/Source"),
+        Target::SinkInputs => class == "Stream/Output/Audio",
+        Target::SourceOutputs => class == "Stream/Input/Audio",
+    }
+}
+
+// PipeWire keeps ports on the device (pulse's card) as routes, not on the node. A node's are the
+// ones going its direction through its own part of the device, for the profile that's active.
+// Gives the device, the node's part of it, and those routes.
+fn node_routes<'a>(objects: &'a [Value], node: &Value, target: Target) -> Option<(&'a Value, u64, Vec<&'a Value>)> {
+    let direction = match target {
+        Target::Sinks => "Output",
+        Target::Sources => "Input",
+        _ => return None,
+    };
+    let props = &node["info"]["props"];
+    // Numbers in newer dumps, strings in some older ones
+    let number = |val: &Value| val.as_u64().or_else(|| val.as_str()?.parse().ok());
+    let (id, part) = (number(&props["device.id"])?, number(&props["card.profile.device"])?);
+    let device = objects.iter().find(|obj| obj["type"] == "PipeWire:Interface:Device" && obj["id"] == id)?;
+    let params = &device["info"]["params"];
+    let profile = params["Profile"][0]["index"].as_u64();
+    let has = |list: &Value, n: u64| list.as_array().into_iter().flatten().any(|val| val == n);
+    let routes = params["EnumRoute"]
+        .as_array()?
+        .iter()
+        .filter(|route| route["direction"] == direction && has(&route["devices"], part))
+        .filter(|route| profile.map(|profile| has(&route["profiles"], profile)).unwrap_or(true))
+        .collect();
+    Some((device, part, routes))
+}
+
+// Route info is a count, then key value pairs
+fn route_info<'a>(route: &'a Value, key: &str) -> Option<&'a str> {
+    let info = route["info"].as_array()?;
+    info.iter().skip(1).step_by(2).position(|k| k == key).and_then(|n| info.get(2 + n * 2)?.as_str())
+}
+
+fn node_ports(objects: &[Value], node: &Value, target: Target) -> (Vec<Port>, Option<String>) {
+    let (device, part, routes) = match node_routes(objects, node, target) {
+        Some(routes) => routes,
+        None => return (Vec::new(), None),
+    };
+    let ports = routes
+        .iter()
+        .filter_map(|route| {
+            Some(Port {
+                name: route["name"].as_str()?.to_string(),
+                description: route["description"].as_str().unwrap_or_default().to_string(),
+                port_type: route_info(route, "port.type").unwrap_or_default().to_string(),
+                available: route["available"] != "no",
+            })
+        })
+        .collect();
+    let active = device["info"]["params"]["Route"]
+        .as_array()
+        .and_then(|routes| routes.iter().find(|route| route["device"] == part))
+        .and_then(|route| route["name"].as_str())
+        .map(String::from);
+    (ports, active)
+}
+
+/// The device, route index and part of the device that `pw-cli set-param <device> Route` needs to
+/// switch `node` to `port`.
+pub fn parse_pw_route(json: &str, node: u32, port: &str, target: Target) -> Option<(u64, u64, u64)> {
+    let objects: Vec<Value> = serde_json::from_str(json).ok()?;
+    let node = objects.iter().find(|obj| obj["type"] == "PipeWire:Interface:Node" && obj["id"] == node)?;
+    let (device, part, routes) = node_routes(&objects, node, target)?;
+    let route = routes.iter().find(|route| route["name"] == port)?;
+    Some((device["id"].as_u64()?, route["index"].as_u64()?, part))
+}
+
+/// All nodes of `target` out of `pw-dump`. None if it isn't a dump.
+pub fn parse_pw_dump(json: &str, target: Target) -> Option<Vec<Device>> {
+    let objects: Vec<Value> = serde_json::from_str(json).ok()?;
+    // (from, to) node ids, for which device a stream is on
+    let links: Vec<(u64, u64)> = objects
+        .iter()
+        .filter(|obj| obj["type"] == "PipeWire:Interface:Link")
+        .filter_map(|link| Some((link["info"]["output-node-id"].as_u64()?, link["info"]["input-node-id"].as_u64()?)))
+        .collect();
+    let mut devices = Vec::new();
+    for node in &objects {
+        if node["type"] != "PipeWire:Interface:Node" || !is_class(node, target) {
+            continue;
+        }
+        let props = &node["info"]["props"];
+        let (index, name) = match (node["id"].as_u64(), props["node.name"].as_str()) {
+            (Some(index), Some(name)) => (index as u32, name.to_string()),
+            _ => continue,
+        };
+        // The first Props has the volumes, any after it are device settings
+        let params = node["info"]["params"]["Props"]
+            .as_array()
+            .and_then(|props| props.iter().find(|p| p.get("channelVolumes").is_some()))
+            .cloned()
+            .unwrap_or(Value::Null);
+        let channels = params["channelMap"].as_array().cloned().unwrap_or_default();
+        let (ports, active_port) = node_ports(&objects, node, target);
+
+        devices.push(Device {
+            index,
+            description: props["node.description"]
+                .as_str()
+                .or_else(|| props["node.nick"].as_str())
+                .unwrap_or(&name)
+                .to_string(),
+            name,
+            mute: params["mute"].as_bool().unwrap_or(false),
+            volume: params["channelVolumes"]
+                .as_array()
+                .map(|volumes| {
+                    volumes
+                        .iter()
+                        .enumerate()
+                        .map(|(n, linear)| {
+                            let linear = linear.as_f64().unwrap_or(0.0);
+                            ChannelVolume {
+                                channel: channels
+                                    .get(n)
+                                    .and_then(Value::as_str)
+                                    .map(channel_name)
+                                    .unwrap_or_else(|| n.to_string()),
+                                // PipeWire stores linear volume, pulse percents are cubic
+                                percent: (linear.cbrt() * 100.0).round() as u32,
+                                db: if linear > 0.0 {
+                                    format!("{:.2} dB", 20.0 * linear.log10())
+                                } else {
+                                    String::from("-inf dB")
+                                },
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ports,
+            active_port,
+            monitor_of: None,
+            connected_to: links
+                .iter()
+                .find_map(|(from, to)| match target {
+                    Target::SinkInputs if *from == u64::from(index) => Some(*to as u32),
+                    Target::SourceOutputs if *to == u64::from(index) => Some(*from as u32),
+                    _ => None,
+                }),
+            properties: properties(props),
+        });
+    }
+    Some(devices)
+}
+
+/// Audio devices (pulse's cards) out of `pw-dump`. None if it isn't a dump.
+pub fn parse_pw_cards(json: &str) -> Option<Vec<Card>> {
+    let objects: Vec<Value> = serde_json::from_str(json).ok()?;
+    let mut cards = Vec::new();
+    for device in &objects {
+        let props = &device["info"]["props"];
+        if device["type"] != "PipeWire:Interface:Device" || props["media.class"] != "Audio/Device" {
+            continue;
+        }
+        let (index, name) = match (device["id"].as_u64(), props["device.name"].as_str()) {
+            (Some(index), Some(name)) => (index as u32, name.to_string()),
+            _ => continue,
+        };
+        let params = &device["info"]["params"];
+        cards.push(Card {
+            index,
+            name,
+            profiles: params["EnumProfile"]
+                .as_array()
+                .map(|profiles| {
+                    profiles
+                        .iter()
+                        .filter_map(|profile| {
+                            Some(Profile {
+                                index: profile["index"].as_u64()? as u32,
+                                name: profile["name"].as_str()?.to_string(),
+                                description: profile["description"].as_str().unwrap_or_default().to_string(),
+                                available: profile["available"] != "no",
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            active_profile: params["Profile"][0]["name"].as_str().map(String::from),
+            properties: properties(props),
+        });
+    }
+    Some(cards)
+}
+
+/// Name of the default sink, or source when `source`, from the "default" metadata in `pw-dump`.
+pub fn parse_pw_default(json: &str, source: bool) -> Option<String> {
+    let objects: Vec<Value> = serde_json::from_str(json).ok()?;
+    let key = if source { "default.audio.source" } else { "default.audio.sink" };
+    objects
+        .iter()
+        .filter(|obj| obj["type"] == "PipeWire:Interface:Metadata" && obj["props"]["metadata.name"] == "default")
+        .flat_map(|obj| obj["metadata"].as_array().cloned().unwrap_or_default())
+        .find(|entry| entry["key"] == key)
+        .and_then(|entry| match &entry["value"] {
+            // Usually JSON, but older versions stored the bare name
+            Value::Object(value) => value.get("name").and_then(Value::as_str).map(String::from),
+            Value::String(value) => serde_json::from_str::<Value>(value)
+                .ok()
+                .and_then(|value| value["name"].as_str().map(String::from))
+                .or_else(|| Some(value.clone())),
+            _ => None,
+        })
+}
+
+/// Talks to PipeWire directly. Reads everything from `pw-dump`, changes things with `wpctl`, or
+/// `pw-cli` for what wpctl can't do.
+pub struct PipeWire<'a> {
+    target: Target,
+    runner: &'a dyn CommandRunner,
+    /// Last dump read. Kept until something gets changed, so reading the default and the devices
+    /// only runs pw-dump once.
+    dump: RefCell<Option<String>>,
+}
+
+impl<'a> PipeWire<'a> {
+    pub fn new(target: Target, runner: &'a dyn CommandRunner) -> PipeWire<'a> {
+        PipeWire {
+            target,
+            runner,
+            dump: RefCell::new(None),
+        }
+    }
+
+    /// For `--backend auto`. A PipeWire backend if PipeWire is running and has audio devices.
+    /// Plenty of pulse systems run PipeWire just for screen sharing.
+    pub fn probe(target: Target, runner: &'a dyn CommandRunner) -> Option<PipeWire<'a>> {
+        let dump = runner.run(&Command::new("pw-dump")).ok()?;
+        if parse_pw_dump(&dump, Target::Sinks)?.is_empty() && parse_pw_dump(&dump, Target::Sources)?.is_empty() {
+            return None;
+        }
+        let pipewire = PipeWire::new(target, runner);
+        pipewire.dump.replace(Some(dump));
+        Some(pipewire)
+    }
+
+    fn dump(&self) -> Result<String, WidgetError> {
+        if let Some(dump) = self.dump.borrow().as_ref() {
+            return Ok(dump.clone());
+        }
+        let dump = self.runner.run(&Command::new("pw-dump"))?;
+        self.dump.replace(Some(dump.clone()));
+        Ok(dump)
+    }
+
+    fn wpctl(&self, args: &[&str]) -> Result<(), WidgetError> {
+        self.dump.replace(None);
+        self.runner.output(&Command::new("wpctl").args(args)).map(|_| ())
+    }
+}
+
+impl Backend for PipeWire<'_> {
+    fn devices(&self) -> Result<Vec<Device>, WidgetError> {
+        parse_pw_dump(&self.dump()?, self.target).ok_or_else(|| WidgetError::Parse(String::from("PipeWire nodes")))
+    }
+
+    fn streams(&self) -> Result<Vec<Device>, WidgetError> {
+        let streams = Target::new(self.target.is_source(), true);
+        parse_pw_dump(&self.dump()?, streams).ok_or_else(|| WidgetError::Parse(String::from("PipeWire nodes")))
+    }
+
+    fn default_device(&self) -> Result<String, WidgetError> {
+        let what = if self.target.is_source() { "default source" } else { "default sink" };
+        parse_pw_default(&self.dump()?, self.target.is_source()).ok_or_else(|| WidgetError::Parse(what.to_string()))
+    }
+
+    fn toggle_mute(&self, dev: &Device) -> Result<(), WidgetError> {
+        self.wpctl(&["set-mute", &dev.index.to_string(), "toggle"])
+    }
+
+    fn make_default(&self, dev: &Device) -> Result<(), WidgetError> {
+        let streams = Target::new(self.target.is_source(), true);
+        let streams = parse_pw_dump(&self.dump()?, streams);
+        self.wpctl(&["set-default", &dev.index.to_string()])?;
+        // Streams follow the default unless something pinned them to a device, so unpin them all.
+        // Except the ones recording a sink's output on purpose.
+        for stream in streams.unwrap_or_default() {
+            if stream.properties.get("stream.capture.sink").map(String::as_str) == Some("true") {
+                continue;
+            }
+            for key in &["target.object", "target.node"] {
+                let _ = self.runner.output(&Command::new("pw-metadata").args(&["-d", &stream.index.to_string(), key]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Ports are routes on the device, wpctl can't switch those.
+    fn set_port(&self, dev: &Device, port: &Port) -> Result<(), WidgetError> {
+        let (device, index, part) = parse_pw_route(&self.dump()?, dev.index, &port.name, self.target)
+            .ok_or_else(|| WidgetError::Parse(format!("PipeWire route {}", port.name)))?;
+        let route = format!("{{ index: {}, device: {}, save: true }}", index, part);
+        self.dump.replace(None);
+        self.runner
+            .output(&Command::new("pw-cli").args(&["set-param", &device.to_string(), "Route", &route]))
+            .map(|_| ())
+    }
+
+    fn cards(&self) -> Result<Vec<Card>, WidgetError> {
+        parse_pw_cards(&self.dump()?).ok_or_else(|| WidgetError::Parse(String::from("PipeWire devices")))
+    }
+
+    fn set_profile(&self, card: &Card, profile: &Profile) -> Result<(), WidgetError> {
+        self.wpctl(&["set-profile", &card.index.to_string(), &profile.index.to_string()])
+    }
+
+    fn set_volume(&self, dev: &Device, percent: f64) -> Result<(), WidgetError> {
+        // wpctl's volumes are cubic too, 1.0 is 100%
+        let volume = ((percent / 100.0).max(0.0) * 10000.0).round() / 10000.0;
+        self.wpctl(&["set-volume", &dev.index.to_string(), &volume.to_string()])
+    }
+
+    /// wpctl only sets every channel at once, so uneven ones go straight to the node's Props.
+    fn set_channels(&self, dev: &Device, percents: &[f64]) -> Result<(), WidgetError> {
+        if percents.iter().all(|percent| (percent - percents[0]).abs() < 0.005) {
+            return self.set_volume(dev, percents.first().copied().unwrap_or(0.0));
+        }
+        // Props want linear volumes
+        let volumes: Vec<String> = percents
+            .iter()
+            .map(|percent| (((percent / 100.0).max(0.0).powi(3) * 1_000_000.0).round() / 1_000_000.0).to_string())
+            .collect();
+        let props = format!("{{ channelVolumes: [ {} ] }}", volumes.join(", "));
+        self.dump.replace(None);
+        self.runner
+            .output(&Command::new("pw-cli").args(&["set-param", &dev.index.to_string(), "Props", &props]))
+            .map(|_| ())
+    }
+}
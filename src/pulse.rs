@@ -0,0 +1,605 @@
+use clap::Clap;
+use crate::audio::{BackendKind, Card, Device, Target, Volume};
+use crate::pactl::pactl;
+use crate::{Block, BlockOpts, ClickBinding, ClickEvent, Command, CommandRunner, Event, Gradient, WidgetError};
+use regex::Regex;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Pulse Audio controller for i3blocks.
+#[derive(Clap)]
+#[clap(author = "Beinsezii")]
+pub struct Opts {
+    /// `$button` arg optionally passed from i3blocks. Read from the environment otherwise.
+    pub button: Option<i32>,
+
+    /// Work on sources (input devices) instead of sinks (output devices)
+    #[clap(long)]
+    source: bool,
+
+    /// Device to work on. Uses default device otherwise. Takes an index, an exact name like
+    /// `alsa_output.pci-0000_00_1f.3.analog-stereo`, part of the description (any case), or a regex
+    /// for either.
+    #[clap(long)]
+    id: Option<String>,
+
+    /// Show and control one app's stream instead of a device, ex `--stream firefox`. Matches the
+    /// app's name or its binary, any case. With `--source`, apps recording instead. The block is
+    /// empty while the app isn't playing anything.
+    #[clap(long, conflicts_with = "id")]
+    stream: Option<String>,
+
+    /// Devices middle click should skip, as regexes for the name or description. Can be given more
+    /// than once. Monitors and unplugged devices are always skipped.
+    #[clap(long, number_of_values = 1)]
+    exclude: Vec<String>,
+
+    /// How much a scroll changes the volume, in percent or dB, ex `5%` or `2dB`. Shift+scroll
+    /// goes 5 times as far.
+    #[clap(long, default_value = "1dB")]
+    step: Volume,
+
+    /// Volume right click resets to, in percent or dB.
+    #[clap(long, default_value = "100%")]
+    reset_to: Volume,
+
+    /// Highest volume scrolling or right click will go to, in percent or dB, ex `120%`. Volumes
+    /// already above it from elsewhere can still be turned down.
+    #[clap(long)]
+    max_volume: Option<Volume>,
+
+    /// Show the left/right balance after the volume, ex `L20%` when the right side is 20% quieter.
+    #[clap(long)]
+    balance: bool,
+
+    /// How far one balance scroll moves it, in percent.
+    #[clap(long, default_value = "5")]
+    balance_step: u32,
+
+    /// Click that moves the balance left. Overall volume stays the same.
+    #[clap(long, default_value = "Ctrl+5")]
+    balance_left: ClickBinding,
+
+    /// Click that moves the balance right.
+    #[clap(long, default_value = "Ctrl+4")]
+    balance_right: ClickBinding,
+
+    /// With `--persist`, show a live peak level bar. Of what's playing on the sink, or what the mic
+    /// hears with `--source`.
+    #[clap(long, conflicts_with = "stream")]
+    pub meter: bool,
+
+    /// Most times a second `--meter` redraws.
+    #[clap(long, default_value = "10")]
+    pub meter_rate: f32,
+
+    /// Width of the `--meter` bar in characters.
+    #[clap(long, default_value = "5")]
+    pub meter_width: usize,
+
+    /// With `--source`, list the apps recording from it after the volume.
+    #[clap(long)]
+    show_recording: bool,
+
+    /// Apps that don't count as recording, by name or binary like `--stream`. Can be given more
+    /// than once. Defaults to pavucontrol, whose level meters record everything it shows.
+    #[clap(long, number_of_values = 1, default_value = "pavucontrol")]
+    ignore_recording: Vec<String>,
+
+    /// Show an icon for the active port, like headphones or speakers.
+    #[clap(long)]
+    show_port: bool,
+
+    /// Port icons by port type for `--show-port`. Ports of other types show their name.
+    #[clap(long, default_value = "headphones:🎧,headset:🎧,speaker:📢,line:🔌,hdmi:📺,spdif:💿,mic:🎤")]
+    port_icons: String,
+
+    /// Click that switches to the next plugged in port, like `Shift+1` or `2`.
+    #[clap(long, default_value = "Shift+1")]
+    port_click: ClickBinding,
+
+    /// Show the active profile of the device's card, like "High Fidelity Playback (A2DP Sink)".
+    #[clap(long)]
+    show_profile: bool,
+
+    /// Card profiles to cycle through, ex `a2dp-sink,headset-head-unit` to flip a bluetooth headset
+    /// between music and calls. Names are from `pactl list cards`.
+    #[clap(long)]
+    profiles: Option<String>,
+
+    /// Click that switches to the next of `--profiles`.
+    #[clap(long, default_value = "Ctrl+1")]
+    profile_click: ClickBinding,
+
+    /// Sound server to talk to. auto, pulse, or pipewire. auto uses PipeWire's own tools if it's
+    /// running, pactl otherwise.
+    #[clap(long, default_value = "auto")]
+    backend: BackendKind,
+
+    /// Color by volume instead of `--color-high` and `--color-low`, ex `0:#268bd2,100:#859900,150:#dc322f`.
+    /// Stops are volume:color, anything in between gets mixed.
+    #[clap(long)]
+    gradient: Option<Gradient>,
+
+    #[clap(long, default_value = "#b58900")]
+    color_high: String,
+
+    #[clap(long, default_value = "#268bd2")]
+    color_low: String,
+
+    #[clap(long, default_value = "#859900")]
+    color_mute: String,
+
+    /// With `--source`, while anything is recording from it.
+    #[clap(long, default_value = "#dc322f")]
+    color_recording: String,
+
+    #[clap(long)]
+    background_high: Option<String>,
+
+    #[clap(long)]
+    background_low: Option<String>,
+
+    #[clap(long)]
+    background_mute: Option<String>,
+
+    #[clap(long)]
+    background_recording: Option<String>,
+
+    #[clap(long = "high", default_value = "🔊")]
+    icon_high: String,
+
+    #[clap(long = "low", default_value = "🔉")]
+    icon_normal: String,
+
+    #[clap(long = "off", default_value = "🔈")]
+    icon_low: String,
+
+    #[clap(long = "mute", default_value = "🔇")]
+    icon_mute: String,
+
+    /// Shown in front of everything while a source is being recorded from.
+    #[clap(long = "recording", default_value = "🔴")]
+    icon_recording: String,
+
+    /// [mic]rophone ic[on]. I'm proud of this one.
+    #[clap(long, default_value = "🎙️")]
+    micon: String,
+
+    #[clap(flatten)]
+    pub block: BlockOpts,
+}
+
+/// Picks the device `--id` means. In order, `id` can be an index, an exact name, part of a
+/// description, or a regex matching a name or description. Matching several devices is an error
+/// listing them, since guessing would mean changing the volume on the wrong thing.
+pub fn find_device<'a>(devices: &'a [Device], id: &str) -> Result<&'a Device, WidgetError> {
+    if let Ok(index) = id.parse::<u32>() {
+        return devices
+            .iter()
+            .find(|dev| dev.index == index)
+            .ok_or_else(|| WidgetError::DeviceNotFound(id.to_string()));
+    }
+    if let Some(dev) = devices.iter().find(|dev| dev.name == id) {
+        return Ok(dev);
+    }
+
+    let lower = id.to_lowercase();
+    let mut matches: Vec<&Device> = devices
+        .iter()
+        .filter(|dev| {
+            dev.description.to_lowercase().contains(&lower)
+                || dev.nick().is_some_and(|nick| nick.to_lowercase().contains(&lower))
+        })
+        .collect();
+    if matches.is_empty() {
+        if let Ok(re) = Regex::new(id) {
+            matches = devices
+                .iter()
+                .filter(|dev| re.is_match(&dev.name) || re.is_match(&dev.description))
+                .collect();
+        }
+    }
+
+    match matches.len() {
+        0 => Err(WidgetError::DeviceNotFound(id.to_string())),
+        1 => Ok(matches[0]),
+        _ => Err(WidgetError::AmbiguousDevice {
+            id: id.to_string(),
+            candidates: matches.iter().map(|dev| dev.name.clone()).collect(),
+        }),
+    }
+}
+
+impl Opts {
+    /// What the block shows.
+    pub fn target(&self) -> Target {
+        Target::new(self.source, self.stream.is_some())
+    }
+
+    /// What `--show-port` shows for `dev`, if anything.
+    pub fn port_icon(&self, dev: &Device) -> Option<String> {
+        let port = dev.port()?;
+        let kind = port.kind();
+        let icon = self
+            .port_icons
+            .split(',')
+            .filter_map(|pair| pair.split_once(':'))
+            .find(|(icon_kind, _)| icon_kind.trim().eq_ignore_ascii_case(&kind))
+            .map(|(_, icon)| icon.trim().to_string());
+        Some(icon.unwrap_or_else(|| port.description.clone()))
+    }
+
+    /// `target` percent, held under `--max-volume` if it's going up from `current`.
+    pub fn limit(&self, current: f64, target: f64) -> f64 {
+        match self.max_volume {
+            Some(max) if target > current => target.min(max.percent().max(current)),
+            _ => target,
+        }
+    }
+}
+
+/// The device after `current` for middle click to switch to, going by name so the order doesn't
+/// change with the index. Skips monitors, unplugged devices, and anything matching `exclude`.
+/// None when there's nothing else to switch to.
+pub fn next_device<'a>(devices: &'a [Device], current: &str, exclude: &[String]) -> Option<&'a Device> {
+    // Not being a valid regex still makes a fine plain string.
+    let exclude: Vec<Regex> = exclude
+        .iter()
+        .map(|pattern| Regex::new(pattern).unwrap_or_else(|_| Regex::new(&regex::escape(pattern)).unwrap()))
+        .collect();
+    let mut candidates: Vec<&Device> = devices
+        .iter()
+        .filter(|dev| !dev.is_monitor() && dev.is_available())
+        .filter(|dev| !exclude.iter().any(|re| re.is_match(&dev.name) || re.is_match(&dev.description)))
+        .collect();
+    candidates.sort_by(|a, b| a.name.cmp(&b.name));
+    let next = candidates
+        .iter()
+        .find(|dev| dev.name.as_str() > current)
+        .or_else(|| candidates.first())?;
+    if next.name == current {
+        None
+    } else {
+        Some(next)
+    }
+}
+
+fn is_app(stream: &Device, app: &str) -> bool {
+    ["application.name", "application.process.binary"]
+        .iter()
+        .filter_map(|key| stream.properties.get(*key))
+        .any(|name| name.eq_ignore_ascii_case(app))
+}
+
+/// Every stream belonging to `app`, by application.name or application.process.binary. Apps like
+/// browsers can have a few going at once.
+pub fn find_streams<'a>(streams: &'a [Device], app: &str) -> Vec<&'a Device> {
+    streams.iter().filter(|stream| is_app(stream, app)).collect()
+}
+
+/// Names of the apps recording from `source`, once each, leaving out the `ignore`d ones. Nothing
+/// for monitors, recording what's playing isn't what the mic light is for.
+pub fn recording_apps(streams: &[Device], source: &Device, ignore: &[String]) -> Vec<String> {
+    if source.is_monitor() {
+        return Vec::new();
+    }
+    let mut apps: Vec<String> = Vec::new();
+    for stream in streams {
+        if stream.connected_to == Some(source.index)
+            && !ignore.iter().any(|app| is_app(stream, app))
+            && !apps.contains(&stream.name)
+        {
+            apps.push(stream.name.clone());
+        }
+    }
+    apps
+}
+
+/// Whether a `pactl subscribe` line could change what the block shows. Anything happening to a
+/// sink, source, or the server itself (default device changes), plus streams when showing those.
+pub fn is_device_event(line: &str, target: Target) -> bool {
+    let re = match target {
+        Target::Sinks => r"^Event '(change|new|remove)' on (sink #|source #|server)",
+        // Apps starting to record light up the block
+        Target::Sources => r"^Event '(change|new|remove)' on (sink #|source #|server|source-output #)",
+        // Streams come and go, and still need the device events for the server restarting
+        _ => r"^Event '(change|new|remove)' on (sink #|source #|server|sink-input #|source-output #)",
+    };
+    Regex::new(re).unwrap().is_match(line)
+}
+
+/// For `--persist`. Watches `pactl subscribe` in the background and refreshes on every device
+/// event, so volume keys outside the bar show up right away. Restarts pactl if it dies, like
+/// when pulse itself restarts.
+pub fn subscribe(tx: Sender<Event>, target: Target) {
+    thread::spawn(move || loop {
+        let cmd = pactl(&["subscribe"]);
+        let _ = cmd.lines(|line| !is_device_event(line, target) || tx.send(Event::Refresh).is_ok());
+        // Redraw so a dead server shows up as an error, then try again.
+        if tx.send(Event::Refresh).is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_secs(1));
+    });
+}
+
+// parec's sample rate for --meter. Plenty for peaks, and cheap.
+const METER_RATE: u32 = 8000;
+
+/// Loudest sample in a chunk of parec's s16le, from 0 to 1.
+pub fn peak(chunk: &[u8]) -> f32 {
+    chunk
+        .chunks_exact(2)
+        .map(|sample| (f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32768.0).abs())
+        .fold(0.0, f32::max)
+}
+
+/// A `width` characters wide bar for `peak`, in eighths of a character. On a -60 to 0 dB scale
+/// like most meters, speech barely moves a linear one.
+pub fn meter_bar(peak: f32, width: usize) -> String {
+    const PARTS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+    let fill = if peak > 0.0 { ((20.0 * peak.log10() + 60.0) / 60.0).clamp(0.0, 1.0) } else { 0.0 };
+    let eighths = (fill * width as f32 * 8.0).round() as usize;
+    let bar = format!("{}{}", "█".repeat(eighths / 8), PARTS[eighths % 8]);
+    // Padded so the block doesn't jump around
+    let pad = width.saturating_sub(bar.chars().count());
+    format!("{}{}", bar, " ".repeat(pad))
+}
+
+/// Where `--meter` should listen. The source itself, or a sink's monitor.
+pub fn meter_source(opts: &Opts, runner: &dyn CommandRunner) -> Result<String, WidgetError> {
+    let name = match &opts.id {
+        Some(id) => find_device(&opts.backend.open(opts.target(), runner).devices()?, id)?.name.clone(),
+        // Follows the default around, like the block
+        None if opts.source => return Ok(String::from("@DEFAULT_SOURCE@")),
+        None => return Ok(String::from("@DEFAULT_MONITOR@")),
+    };
+    Ok(if opts.source { name } else { format!("{}.monitor", name) })
+}
+
+/// `--meter`'s sampling in the background, and the latest peak it saw.
+pub struct Meter {
+    // f32 bits, there's no atomic float
+    level: AtomicU32,
+    // Wake ups sent for the meter that haven't been drawn yet
+    ticks: AtomicUsize,
+}
+
+impl Meter {
+    /// Starts recording `source` with parec, and wakes `tx` up with a new peak `rate` times a
+    /// second. Restarts parec if it dies, like `subscribe` does pactl.
+    pub fn start(tx: Sender<Event>, source: String, rate: f32) -> Arc<Meter> {
+        let meter = Arc::new(Meter {
+            level: AtomicU32::new(0),
+            ticks: AtomicUsize::new(0),
+        });
+        let shared = meter.clone();
+        let samples = (METER_RATE as f32 / rate.max(0.1)).max(1.0) as usize;
+        thread::spawn(move || loop {
+            let cmd = Command::new("parec").args(&[
+                "--raw",
+                "--format=s16le",
+                "--channels=1",
+                &format!("--rate={}", METER_RATE),
+                &format!("--latency-msec={}", (samples as u32 * 1000 / METER_RATE).max(1)),
+                // So the mic indicator can leave it out
+                "--client-name=pulse-status",
+                "-d",
+                &source,
+            ]);
+            let _ = cmd.chunks(samples * 2, |chunk| {
+                shared.level.store(peak(chunk).to_bits(), Ordering::Relaxed);
+                shared.ticks.fetch_add(1, Ordering::SeqCst);
+                tx.send(Event::Refresh).is_ok()
+            });
+            shared.level.store(0, Ordering::Relaxed);
+            if tx.send(Event::Refresh).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_secs(1));
+        });
+        meter
+    }
+
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Whether a redraw is only for the meter, so the last block is still good and nothing needs
+    /// rereading. Uses up one meter wake up each time it says yes.
+    pub fn take_tick(&self) -> bool {
+        self.ticks.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |ticks| ticks.checked_sub(1)).is_ok()
+    }
+
+    /// `block` with the bar after it.
+    pub fn draw(&self, mut block: Block, width: usize) -> Block {
+        let bar = meter_bar(self.level(), width);
+        block.full_text = format!("{} {}", block.full_text, bar);
+        block.short_text = block.short_text.map(|short| format!("{} {}", short, bar));
+        block
+    }
+}
+
+/// How `--balance` shows it. "C" when centered, else the louder side and how much quieter the other
+/// one is, ex "L20%".
+pub fn balance(balance: f64) -> String {
+    let percent = (balance.abs() * 100.0).round();
+    if percent == 0.0 {
+        String::from("C")
+    } else if balance < 0.0 {
+        format!("L{}%", percent)
+    } else {
+        format!("R{}%", percent)
+    }
+}
+
+/// `click` is what to do before reading the device, if anything.
+pub fn run(opts: &Opts, click: Option<ClickEvent>, runner: &dyn CommandRunner) -> Result<Block, WidgetError> {
+    let micon = if opts.source { opts.micon.as_str() } else { "" };
+    let backend = opts.backend.open(opts.target(), runner);
+
+    let mut devices = backend.devices()?;
+    let mut device = match (&opts.stream, &opts.id) {
+        (Some(app), _) => app.clone(),
+        // Remember the name, it doesn't change between reboots or rereads like the index
+        (None, Some(id)) => find_device(&devices, id)?.name.clone(),
+        (None, None) => backend.default_device()?,
+    };
+    // Everything being shown and controlled. Just the one device, unless it's an app's streams.
+    let select = |devices: &[Device], device: &str| -> Result<Vec<Device>, WidgetError> {
+        match &opts.stream {
+            Some(_) => Ok(find_streams(devices, device).into_iter().cloned().collect()),
+            None => match devices.iter().find(|dev| dev.name == device) {
+                Some(dev) => Ok(vec![dev.clone()]),
+                None => Err(WidgetError::DeviceNotFound(device.to_string())),
+            },
+        }
+    };
+    let mut selected = select(&devices, &device)?;
+    let card = |dev: &Device| -> Result<Option<Card>, WidgetError> {
+        Ok(backend.cards()?.into_iter().find(|card| card.owns(dev)))
+    };
+
+    if let (Some(click), false) = (click, selected.is_empty()) {
+        // Shift+scroll for bigger steps
+        let step = if click.has_modifier("Shift") { opts.step.times(5.0) } else { opts.step };
+        // 1 = LMB, 2 = MMB, 3 = RMB, 4 = ScrollUp, 5 = ScrollDown
+        match click.button {
+            // Streams don't have ports
+            _ if opts.stream.is_none() && opts.port_click.matches(&click) => {
+                if let Some(port) = selected[0].next_port() {
+                    let _ = backend.set_port(&selected[0], port);
+                }
+            }
+            _ if opts.stream.is_none() && opts.profiles.is_some() && opts.profile_click.matches(&click) => {
+                let wanted: Vec<&str> = opts.profiles.iter().flat_map(|list| list.split(',')).map(str::trim).collect();
+                if let Some(card) = card(&selected[0])? {
+                    if let Some(profile) = card.next_profile(&wanted) {
+                        let _ = backend.set_profile(&card, profile);
+                        // The card's sinks and sources get swapped out for the new profile's, so
+                        // look the device up again
+                        devices = backend.devices()?;
+                        device = match &opts.id {
+                            Some(id) => find_device(&devices, id)?.name.clone(),
+                            None => backend.default_device()?,
+                        };
+                    }
+                }
+            }
+            _ if opts.balance_left.matches(&click) || opts.balance_right.matches(&click) => {
+                let step = f64::from(opts.balance_step) / 100.0;
+                let step = if opts.balance_left.matches(&click) { -step } else { step };
+                for dev in &selected {
+                    let _ = backend.set_channels(dev, &dev.balanced(dev.balance() + step));
+                }
+            }
+            // Switching the default doesn't mean anything for streams
+            2 if opts.stream.is_none() => {
+                let default = match &opts.id {
+                    Some(_) => backend.default_device()?,
+                    None => device.clone(),
+                };
+                if let Some(next) = next_device(&devices, &default, &opts.exclude) {
+                    let _ = backend.make_default(next);
+                    // Follow the default along, unless showing a specific device
+                    if opts.id.is_none() {
+                        device = next.name.clone();
+                    }
+                }
+            }
+            1 | 3 | 4 | 5 => {
+                for dev in &selected {
+                    let current = f64::from(dev.loudest());
+                    let _ = match click.button {
+                        1 => backend.toggle_mute(dev),
+                        3 => backend.set_volume(dev, opts.limit(current, opts.reset_to.percent())),
+                        // Scrolling moves every channel along, so the balance stays
+                        4 => backend.set_channels(dev, &dev.scaled(opts.limit(current, step.step(current, true)))),
+                        _ => backend.set_channels(dev, &dev.scaled(opts.limit(current, step.step(current, false)))),
+                    };
+                }
+            }
+            _ => (),
+        }
+        // Whatever it did shows up when the devices are read again.
+        devices = backend.devices()?;
+        selected = select(&devices, &device)?;
+    }
+
+    // Hide the block until the app plays something
+    let status = match selected.first() {
+        Some(status) => status,
+        None => return Ok(Block::new("")),
+    };
+
+    // Loudest channel's volume, like pulse. Just the first would hide a sink turned all the way to
+    // one side.
+    let channel = status
+        .loudest_channel()
+        .ok_or_else(|| WidgetError::Parse(String::from("device volume")))?;
+    let volume = format!("{}% / {}", channel.percent, channel.db);
+    let intvol = channel.percent;
+
+    let recording = if opts.source && opts.stream.is_none() {
+        // Old pactl prints nothing at all when nothing's recording, which comes back as an error
+        let streams = backend.streams().unwrap_or_default();
+        let mut ignore = opts.ignore_recording.clone();
+        // --meter's own parec
+        ignore.push(String::from("pulse-status"));
+        recording_apps(&streams, status, &ignore)
+    } else {
+        Vec::new()
+    };
+    let rec = if recording.is_empty() { "" } else { opts.icon_recording.as_str() };
+
+    let icon: &str;
+    let mut color = None;
+    let mut background = None;
+    if status.mute {
+        icon = &opts.icon_mute;
+        color = Some(opts.color_mute.clone());
+        background = opts.background_mute.clone();
+    } else if intvol > 100 {
+        color = Some(opts.color_high.clone());
+        background = opts.background_high.clone();
+        icon = &opts.icon_high;
+    } else if intvol < 100 {
+        color = Some(opts.color_low.clone());
+        background = opts.background_low.clone();
+        icon = &opts.icon_low;
+    } else {
+        icon = &opts.icon_normal;
+    }
+    // A gradient takes over from the fixed volume colors
+    if let (Some(gradient), false) = (&opts.gradient, status.mute) {
+        color = Some(gradient.color(intvol as f32));
+    }
+    // Recording trumps the volume colors, but a muted mic isn't recording anything
+    if !recording.is_empty() && !status.mute {
+        color = Some(opts.color_recording.clone());
+        background = opts.background_recording.clone();
+    }
+
+    let port = if opts.show_port { opts.port_icon(status).unwrap_or_default() } else { String::new() };
+    let mut full_text = format!("{}{}{}{} {}", rec, micon, port, icon, volume);
+    if opts.balance {
+        full_text = format!("{} {}", full_text, balance(status.balance()));
+    }
+    if opts.show_recording && !recording.is_empty() {
+        full_text = format!("{} ({})", full_text, recording.join(", "));
+    }
+    if opts.show_profile && opts.stream.is_none() {
+        if let Some(profile) = card(status)?.as_ref().and_then(Card::profile) {
+            full_text = format!("{} [{}]", full_text, profile.description);
+        }
+    }
+    let mut block = Block::new(&full_text);
+    block.short_text = Some(format!("{}{}{} {}%", rec, micon, icon, intvol));
+    block.color = color;
+    block.background = background;
+    Ok(block)
+}
@@ -0,0 +1,150 @@
+use crate::{Block, BlockOpts, ClickEvent, Command, CommandRunner, Gradient, WidgetError};
+use clap::Clap;
+use regex::Regex;
+
+/// Quod Libet controller for i3blocks. Best used with quodlibet-volume.
+#[derive(Clap)]
+#[clap(author = "Beinsezii")]
+pub struct StatusOpts {
+    /// `$button` arg optionally passed from i3blocks. Read from the environment otherwise.
+    pub button: Option<i32>,
+
+    #[clap(long = "play", default_value = "▶")]
+    icon_play: String,
+
+    #[clap(long = "pause", default_value = "⏸️")]
+    icon_pause: String,
+
+    #[clap(long = "stop", default_value = "⏹️")]
+    icon_stop: String,
+
+    /// Format string for the long view
+    #[clap(long, short, default_value="<title><artist| / <artist>><album| / <album>>")]
+    long: String,
+
+    /// Format string for the short view
+    #[clap(long, short, default_value="<title>")]
+    short: String,
+
+    #[clap(flatten)]
+    pub block: BlockOpts,
+}
+
+/// Quod Libet volume controller for i3blocks. Best used with quodlibet-status.
+#[derive(Clap)]
+#[clap(author = "Beinsezii")]
+pub struct VolumeOpts {
+    /// $button arg optionally passed from i3blocks. Read from the environment otherwise.
+    pub button: Option<i32>,
+
+    #[clap(long = "high", default_value = "🔊")]
+    icon_high: String,
+
+    #[clap(long = "normal", default_value = "🔉")]
+    icon_normal: String,
+
+    #[clap(long = "low", default_value = "🔈")]
+    icon_low: String,
+
+    /// Color by volume, ex `0:#268bd2,100:#dc322f`. Stops are volume:color, anything in between gets
+    /// mixed.
+    #[clap(long)]
+    gradient: Option<Gradient>,
+
+    #[clap(flatten)]
+    pub block: BlockOpts,
+}
+
+/// `click` is what to do before reading the status, if anything.
+pub fn status(opts: &StatusOpts, click: Option<ClickEvent>, runner: &dyn CommandRunner) -> Result<Block, WidgetError> {
+    match click {
+        // 1 = LMB, 2 = MMB, 3 = RMB, 4 = ScrollUp, 5 = ScrollDown
+        Some(click) => match click.button {
+            1 => runner.shell("quodlibet", &["--play-pause"]),
+            2 => runner.shell("quodlibet", &["--toggle-window"]),
+            3 => runner.shell("quodlibet", &["--stop"]),
+            4 => runner.shell("quodlibet", &["--previous"]),
+            5 => runner.shell("quodlibet", &["--next"]),
+            _ => None,
+        },
+        None => None,
+    };
+
+    // it takes about 0.1 to 0.15 seconds for a single run of quodlibet, so threads.
+    let (status, long, short) = std::thread::scope(|scope| {
+        let status_t = scope.spawn(|| runner.run(&Command::new("quodlibet").arg("--status")));
+        let long_t = scope.spawn(|| runner.shell("quodlibet", &["--print-playing", &opts.long]));
+        let short_t = scope.spawn(|| runner.shell("quodlibet", &["--print-playing", &opts.short]));
+        (status_t.join().unwrap(), long_t.join().unwrap(), short_t.join().unwrap())
+    });
+
+    let status = status?;
+    let long = match long {
+        None => "Long String Error".to_string(),
+        Some(result) => result,
+    };
+    let short = match short {
+        None => "Short String Error".to_string(),
+        Some(result) => result,
+    };
+
+    // if somehow status doesn't quit the app cause it's blank, but it's *also* not playing or
+    // paused, icon is "?" aka "idk what the hell's going on this shouldn't be possible"
+    let mut icon = "?";
+    if status.starts_with("playing") {
+        icon = &opts.icon_play;
+    } else if status.starts_with("paused") {
+        // quodlibet displays "paused" for both pause and stop states, so it simply checks if the
+        // song is 'on 0.000' seconds aka hasn't started yet.
+        if status.ends_with("on 0.000") {
+            icon = &opts.icon_stop;
+        } else {
+            icon = &opts.icon_pause;
+        }
+    }
+
+    let mut block = Block::new(&format!("{} {}", icon, long));
+    block.short_text = Some(format!("{} {}", icon, short));
+    Ok(block)
+}
+
+/// `click` is what to do before reading the volume, if anything.
+pub fn volume(opts: &VolumeOpts, click: Option<ClickEvent>, runner: &dyn CommandRunner) -> Result<Block, WidgetError> {
+    match click {
+        Some(click) => match click.button {
+            // 1 = LMB, 2 = MMB, 3 = RMB, 4 = ScrollUp, 5 = ScrollDown
+            1 | 4 => runner.shell("quodlibet", &["--volume-up"]),
+            3 | 5 => runner.shell("quodlibet", &["--volume-down"]),
+            _ => None,
+        },
+        None => None,
+    };
+
+    let status = match runner.shell("quodlibet", &["--status"]) {
+        Some(val) => val,
+        // quodlbet-status will show the error, so volume prints a single space to keep the
+        // separator.
+        None => return Ok(Block::new(" ")),
+    };
+
+    // I don't know how an unwrap would fail on the new, considering the regex is hardcoded.
+    let re = Regex::new(r"\d\.\d{3}").unwrap().find(status.as_str());
+
+    let volume: f32 = match re.map(|re| re.as_str().parse::<f32>()) {
+        Some(Ok(volume)) => volume * 100.0,
+        _ => return Err(WidgetError::Parse(String::from("volume"))),
+    };
+
+    let icon: &str;
+    if volume > 66.0 {
+        icon = &opts.icon_high;
+    } else if volume < 34.0 {
+        icon = &opts.icon_low;
+    } else {
+        icon = &opts.icon_normal;
+    }
+
+    let mut block = Block::new(&format!("{} {:.0}%", icon, volume));
+    block.color = opts.gradient.as_ref().map(|gradient| gradient.color(volume));
+    Ok(block)
+}
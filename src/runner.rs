@@ -0,0 +1,113 @@
+use crate::command::{Command, CommandOutput};
+use crate::error::WidgetError;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Whatever actually runs the widgets' commands. Widgets only talk to external programs through
+/// this, so tests can swap in a `FixtureRunner` with recorded output.
+pub trait CommandRunner: Sync {
+    fn output(&self, cmd: &Command) -> Result<CommandOutput, WidgetError>;
+
+    /// Same as `Command::run`.
+    fn run(&self, cmd: &Command) -> Result<String, WidgetError> {
+        cmd.check(self.output(cmd)?)
+    }
+
+    /// Same as `quickshell::shell`. For fire-and-forget commands like button actions.
+    fn shell(&self, command: &str, args: &[&str]) -> Option<String> {
+        let output = self.output(&Command::new(command).args(args)).ok()?;
+        let result = output.stdout.trim();
+        if output.timed_out || result.is_empty() {None} else {Some(result.to_string())}
+    }
+}
+
+/// Runs commands for real.
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, cmd: &Command) -> Result<CommandOutput, WidgetError> {
+        cmd.output()
+    }
+}
+
+/// Replays recorded output, looked up by the full command line. Anything it doesn't have a
+/// recording for acts like a missing program. Keeps a log of every command it was asked to run,
+/// so button actions can be checked too.
+#[derive(Debug, Default)]
+pub struct FixtureRunner {
+    outputs: HashMap<String, CommandOutput>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl FixtureRunner {
+    pub fn new() -> FixtureRunner {
+        FixtureRunner::default()
+    }
+
+    /// Records a successful run of `line` that printed `stdout`.
+    pub fn with_stdout(self, line: &str, stdout: &str) -> FixtureRunner {
+        self.with_output(
+            line,
+            CommandOutput {
+                code: Some(0),
+                stdout: stdout.to_string(),
+                ..CommandOutput::default()
+            },
+        )
+    }
+
+    pub fn with_output(mut self, line: &str, output: CommandOutput) -> FixtureRunner {
+        self.outputs.insert(line.to_string(), output);
+        self
+    }
+
+    /// Every command line run so far, in order.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for FixtureRunner {
+    fn output(&self, cmd: &Command) -> Result<CommandOutput, WidgetError> {
+        let line = cmd.to_string();
+        self.calls.lock().unwrap().push(line.clone());
+        match self.outputs.get(&line) {
+            Some(output) => Ok(output.clone()),
+            None => Err(WidgetError::CommandNotFound(cmd.program.clone())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod runner_tests {
+    use super::*;
+    #[test]
+    fn replay() {
+        let runner = FixtureRunner::new().with_stdout("pactl info", "Server Name: test\n");
+        assert_eq!(runner.run(&Command::new("pactl").arg("info")), Ok(String::from("Server Name: test")));
+        assert_eq!(runner.shell("pactl", &["set-sink-mute", "0", "toggle"]), None);
+        assert_eq!(runner.calls(), vec!["pactl info", "pactl set-sink-mute 0 toggle"]);
+    }
+
+    #[test]
+    fn replay_failure() {
+        let failed = CommandOutput {
+            code: Some(1),
+            stderr: String::from("No devices were found"),
+            ..CommandOutput::default()
+        };
+        let runner = FixtureRunner::new().with_output("nvidia-smi -q -i 3", failed);
+        assert_eq!(
+            runner.run(&Command::new("nvidia-smi").args(&["-q", "-i", "3"])),
+            Err(WidgetError::CommandFailed {
+                command: String::from("nvidia-smi -q -i 3"),
+                stderr: String::from("No devices were found"),
+            })
+        );
+    }
+
+    #[test]
+    fn system() {
+        assert_eq!(SystemRunner.shell("echo", &["hi"]), Some(String::from("hi")));
+    }
+}
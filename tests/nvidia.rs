@@ -0,0 +1,377 @@
+use clap::Clap;
+use quickshell::nvidia::{run, Clocks, Field, GpuStats, Opts, Throttle};
+use quickshell::{Command, CommandOutput, CommandRunner, FixtureRunner, Gradient, WidgetError};
+
+const STATUS: &str = include_str!("fixtures/nvidia-smi-q.txt");
+// Every field, asked for with `--gpu-name --encode --decode`
+const QUERY: &str = include_str!("fixtures/nvidia-smi-query-gpu.csv");
+const THROTTLE: &str = "clocks_throttle_reasons.gpu_idle,clocks_throttle_reasons.hw_slowdown,\
+clocks_throttle_reasons.hw_thermal_slowdown,clocks_throttle_reasons.hw_power_brake_slowdown,\
+clocks_throttle_reasons.sw_thermal_slowdown,clocks_throttle_reasons.sw_power_cap,clocks_throttle_reasons.sync_boost";
+
+fn opts(args: &[&str]) -> Opts {
+    Opts::parse_from(std::iter::once(&"nvidia-status").chain(args))
+}
+
+/// A driver too old for most of `--query-gpu`. Turns down any query without a recording, like
+/// nvidia-smi does for fields it doesn't know, so everything else comes out of the `-q` dump.
+struct OldDriver(FixtureRunner);
+
+impl OldDriver {
+    fn with_stdout(self, line: &str, stdout: &str) -> OldDriver {
+        OldDriver(self.0.with_stdout(line, stdout))
+    }
+
+    fn with_output(self, line: &str, output: CommandOutput) -> OldDriver {
+        OldDriver(self.0.with_output(line, output))
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.0.calls()
+    }
+}
+
+impl CommandRunner for OldDriver {
+    fn output(&self, cmd: &Command) -> Result<CommandOutput, WidgetError> {
+        match self.0.output(cmd) {
+            Err(WidgetError::CommandNotFound(_)) if cmd.args.iter().any(|arg| arg.starts_with("--query-gpu")) => {
+                Ok(CommandOutput {
+                    code: Some(2),
+                    stdout: String::from("Field \"clocks_throttle_reasons.gpu_idle\" is not a valid field to query."),
+                    ..CommandOutput::default()
+                })
+            }
+            result => result,
+        }
+    }
+}
+
+fn runner(status: &str) -> OldDriver {
+    OldDriver(FixtureRunner::new().with_stdout("nvidia-smi -q -i 0", status))
+}
+
+fn query(fields: &str) -> String {
+    format!("nvidia-smi --query-gpu={},{} --format=csv,noheader,nounits -i 0", fields, THROTTLE)
+}
+
+#[test]
+fn default_view() {
+    let block = run(&opts(&[]), &runner(STATUS)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18"));
+    assert_eq!(block.color, None);
+}
+
+#[test]
+fn all_fields() {
+    let args = ["--gpu-name", "--encode", "--decode", "--perc-mem", "--short-temp"];
+    let block = run(&opts(&args), &runner(STATUS)).unwrap();
+    assert_eq!(block.full_text, "GTX 1070: GPU 37%|MEM 18%|58C|ENC 4%|VID 0%");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18|58C"));
+}
+
+#[test]
+fn nothing_shown() {
+    let block = run(&opts(&["--no-util", "--no-mem", "--no-temp"]), &runner(STATUS)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: ");
+}
+
+#[test]
+fn idle_color() {
+    let status = STATUS.replace("Idle                        : Not Active", "Idle                        : Active");
+    let block = run(&opts(&["--background-idle", "#002b36"]), &runner(&status)).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#268bd2"));
+    assert_eq!(block.background.as_deref(), Some("#002b36"));
+}
+
+#[test]
+fn missing_field() {
+    let status = STATUS.replace("Encoder                     : 4 %", "");
+    assert_eq!(
+        run(&opts(&["--encode"]), &runner(&status)),
+        Err(WidgetError::Parse(String::from("encoder utilization")))
+    );
+}
+
+#[test]
+fn no_nvidia_smi() {
+    let runner = FixtureRunner::new();
+    assert_eq!(run(&opts(&[]), &runner), Err(WidgetError::CommandNotFound(String::from("nvidia-smi"))));
+    // -q wouldn't find it either
+    assert_eq!(runner.calls().len(), 1);
+}
+
+#[test]
+fn query_timeout() {
+    // Hung, so don't go and wait on -q as well
+    let hung = CommandOutput { timed_out: true, ..CommandOutput::default() };
+    let runner = runner(STATUS).with_output(&query("utilization.gpu,memory.used,memory.total,temperature.gpu"), hung);
+    assert!(matches!(run(&opts(&[]), &runner), Err(WidgetError::CommandTimedOut(_))));
+    assert_eq!(runner.calls().len(), 1);
+}
+
+#[test]
+fn gradient_metrics() {
+    let block = run(&opts(&["--gradient", "0:#268bd2,37:#b58900,100:#dc322f"]), &runner(STATUS)).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#b58900"));
+    // Works on hidden metrics too
+    let args = ["--no-temp", "--gradient-metric", "temp", "--gradient", "40:#268bd2,58:#dc322f"];
+    assert_eq!(run(&opts(&args), &runner(STATUS)).unwrap().color.as_deref(), Some("#dc322f"));
+    let gradient: Gradient = "0:#000000,100:#ffffff".parse().unwrap();
+    let args = ["--gradient-metric", "mem", "--gradient", "0:#000000,100:#ffffff"];
+    assert_eq!(run(&opts(&args), &runner(STATUS)).unwrap().color, Some(gradient.color(1436.0 / 8119.0 * 100.0)));
+    // Idle still wins
+    let status = STATUS.replace("Idle                        : Not Active", "Idle                        : Active");
+    let block = run(&opts(&["--gradient", "0:#dc322f"]), &runner(&status)).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#268bd2"));
+}
+
+fn throttling(reasons: &[&str]) -> String {
+    reasons.iter().fold(STATUS.to_string(), |status, reason| {
+        let line = format!("{:<28}: Not Active", reason);
+        status.replace(&line, &format!("{:<28}: Active", reason))
+    })
+}
+
+#[test]
+fn throttle_colors() {
+    let block = run(&opts(&["--throttle-reason"]), &runner(&throttling(&["SW Power Cap"]))).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#b58900"));
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|POWER CAP");
+    // Worst reason wins
+    let status = throttling(&["SW Power Cap", "SW Thermal Slowdown"]);
+    let block = run(&opts(&["--background-throttle", "#002b36"]), &runner(&status)).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#cb4b16"));
+    assert_eq!(block.background.as_deref(), Some("#002b36"));
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C");
+    // Only the sub-reason is set on some drivers
+    let status = throttling(&["    HW Power Brake Slowdown", "Sync Boost"]);
+    assert_eq!(run(&opts(&[]), &runner(&status)).unwrap().color.as_deref(), Some("#dc322f"));
+    let args = ["--color-sync-boost", "#6c71c4"];
+    assert_eq!(run(&opts(&args), &runner(&throttling(&["Sync Boost"]))).unwrap().color.as_deref(), Some("#6c71c4"));
+    // Not throttling while idle
+    let status = throttling(&["Idle", "SW Power Cap"]);
+    assert_eq!(run(&opts(&[]), &runner(&status)).unwrap().color.as_deref(), Some("#268bd2"));
+}
+
+#[test]
+fn event_reasons() {
+    // What drivers 535 and up call it
+    let status = throttling(&["SW Thermal Slowdown"]).replace("Clocks Throttle Reasons", "Clocks Event Reasons");
+    let block = run(&opts(&["--throttle-reason"]), &runner(&status)).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#cb4b16"));
+    assert!(block.full_text.ends_with("|THERMAL"));
+}
+
+#[test]
+fn query_gpu() {
+    let line = "37, 1436, 8119, 58, Not Active, Not Active, Not Active, Not Active, Not Active, Active, Not Active";
+    let runner = FixtureRunner::new().with_stdout(&query("utilization.gpu,memory.used,memory.total,temperature.gpu"), line);
+    let block = run(&opts(&["--throttle-reason"]), &runner).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|POWER CAP");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18"));
+    assert_eq!(block.color.as_deref(), Some("#b58900"));
+    // Never touched the slow dump
+    assert_eq!(runner.calls().len(), 1);
+
+    let fields = "name,utilization.gpu,memory.used,memory.total,temperature.gpu,utilization.encoder,utilization.decoder";
+    let runner = FixtureRunner::new().with_stdout(&query(fields), QUERY);
+    let args = ["--gpu-name", "--encode", "--decode", "--perc-mem", "--short-temp"];
+    let block = run(&opts(&args), &runner).unwrap();
+    assert_eq!(block.full_text, "GTX 1070: GPU 37%|MEM 18%|58C|ENC 4%|VID 0%");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18|58C"));
+}
+
+#[test]
+fn query_hidden_gradient_metric() {
+    let runner = FixtureRunner::new().with_stdout(&query("temperature.gpu"), &format!("58{}", ", Not Active".repeat(7)));
+    let block = run(&opts(&["--no-util", "--no-mem", "--no-temp", "--gradient-metric", "temp", "--gradient", "58:#dc322f"]), &runner).unwrap();
+    assert_eq!(block.full_text, "GPU 0: ");
+    assert_eq!(block.color.as_deref(), Some("#dc322f"));
+}
+
+#[test]
+fn query_fallback() {
+    // Drivers that don't know a field refuse the whole query
+    let refused = CommandOutput {
+        code: Some(2),
+        stdout: String::from("Field \"clocks_throttle_reasons.gpu_idle\" is not a valid field to query."),
+        ..CommandOutput::default()
+    };
+    let fields = "utilization.gpu,memory.used,memory.total,temperature.gpu";
+    let block = run(&opts(&[]), &runner(STATUS).with_output(&query(fields), refused)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C");
+    // Ones that do can still not support it on this GPU
+    let runner = runner(STATUS).with_stdout(&query("utilization.encoder"), &format!("[N/A]{}", ", Not Active".repeat(7)));
+    let block = run(&opts(&["--no-util", "--no-mem", "--no-temp", "--encode"]), &runner).unwrap();
+    assert_eq!(block.full_text, "GPU 0: ENC 4%");
+    assert_eq!(runner.calls().last().map(String::as_str), Some("nvidia-smi -q -i 0"));
+}
+
+#[test]
+fn both_parsers_agree() {
+    let fields = [Field::Name, Field::Util, Field::Mem, Field::Temp, Field::Encoder, Field::Decoder, Field::Throttle];
+    let stats = GpuStats::from_csv(QUERY.trim(), &fields).unwrap();
+    assert_eq!(stats, GpuStats::from_status(STATUS, &fields).unwrap());
+    assert_eq!(stats.name.as_deref(), Some("GTX 1070"));
+    assert_eq!(stats.memory, Some((1436.0, 8119.0)));
+    let line = "Active, Not Active, Not Active, Active, Not Active, Active, Not Active";
+    let stats = GpuStats::from_csv(line, &[Field::Throttle]).unwrap();
+    assert!(stats.idle);
+    assert_eq!(stats.throttle, vec![Throttle::HwSlowdown, Throttle::PowerCap]);
+    assert_eq!(GpuStats::from_csv("37", &[Field::Mem]), Err(WidgetError::Parse(String::from("memory"))));
+}
+
+// Four GPUs: working, power capped, hot and throttling, and idle
+const QUERY_ALL: &str = include_str!("fixtures/nvidia-smi-query-gpu-all.csv");
+
+fn runner_all() -> FixtureRunner {
+    let line = query("utilization.gpu,memory.used,memory.total,temperature.gpu").replace(" -i 0", "");
+    FixtureRunner::new().with_stdout(&line, QUERY_ALL)
+}
+
+#[test]
+fn all_gpus() {
+    let block = run(&opts(&["--all", "--throttle-reason"]), &runner_all()).unwrap();
+    assert_eq!(block.full_text, "0:45%/81%/71C 1:98%/94%/83C 2:97%/90%/86C 3:0%/1%/35C THERMAL");
+    assert_eq!(block.short_text.as_deref(), Some("0:45% 1:98% 2:97% 3:0%"));
+    // The worst one
+    assert_eq!(block.color.as_deref(), Some("#cb4b16"));
+    let temps: Vec<String> = QUERY_ALL.lines().map(|line| line.splitn(4, ", ").last().unwrap().to_string()).collect();
+    let runner = FixtureRunner::new().with_stdout(&query("temperature.gpu").replace(" -i 0", ""), &temps.join("\n"));
+    let block = run(&opts(&["--all", "--no-util", "--no-mem"]), &runner).unwrap();
+    assert_eq!(block.short_text.as_deref(), Some("0:71C 1:83C 2:86C 3:35C"));
+}
+
+#[test]
+fn summary() {
+    let block = run(&opts(&["--summary", "--short-temp"]), &runner_all()).unwrap();
+    assert_eq!(block.full_text, "4 GPUs: GPU 60%|65300/98304MiB|86C");
+    assert_eq!(block.short_text.as_deref(), Some("4 GPUs: GPU 60|MEM 66|86C"));
+    // Gradient goes by the highest, here 86C
+    let args = ["--summary", "--gradient-metric", "temp", "--gradient", "80:#000000,86:#ffffff"];
+    let status = QUERY_ALL.replace(", Active", ", Not Active");
+    let line = query("utilization.gpu,memory.used,memory.total,temperature.gpu").replace(" -i 0", "");
+    let block = run(&opts(&args), &FixtureRunner::new().with_stdout(&line, &status)).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#ffffff"));
+}
+
+#[test]
+fn all_gpus_fallback() {
+    // Second GPU, same as the first but busier
+    let second = &STATUS[STATUS.find("GPU 00000000").unwrap()..];
+    let second = second
+        .replace("GPU 00000000:01:00.0", "GPU 00000000:02:00.0")
+        .replace("Gpu                         : 37 %", "Gpu                         : 99 %")
+        .replace("GPU Current Temp            : 58 C", "GPU Current Temp            : 80 C");
+    let dump = format!("{}{}", STATUS.replace("Attached GPUs                       : 1", "Attached GPUs                       : 2"), second);
+    let runner = OldDriver(FixtureRunner::new().with_stdout("nvidia-smi -q", &dump));
+    let block = run(&opts(&["--all"]), &runner).unwrap();
+    assert_eq!(block.full_text, "0:37%/18%/58C 1:99%/18%/80C");
+    let block = run(&opts(&["--summary", "--power-draw", "--power-limit", "--fan"]), &runner).unwrap();
+    assert_eq!(block.full_text, "2 GPUs: GPU 68%|2872/16238MiB|80C|122/302W|FAN 33%");
+    // Everything idle
+    let idle = dump.replace("Idle                        : Not Active", "Idle                        : Active");
+    let block = run(&opts(&["--all"]), &OldDriver(FixtureRunner::new().with_stdout("nvidia-smi -q", &idle))).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#268bd2"));
+}
+
+#[test]
+fn power_and_fan() {
+    let args = ["--power-draw", "--power-limit", "--fan"];
+    let block = run(&opts(&args), &runner(STATUS)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|61/151W|FAN 33%");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18|61/151W|FAN 33"));
+    let block = run(&opts(&["--power-limit", "--short-temp"]), &runner(STATUS)).unwrap();
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18|58C|LIM 151W"));
+
+    let fields = "utilization.gpu,memory.used,memory.total,temperature.gpu,power.draw,power.limit,fan.speed";
+    let line = format!("37, 1436, 8119, 58, 61.22, 151.00, 33{}", ", Not Active".repeat(7));
+    let queried = FixtureRunner::new().with_stdout(&query(fields), &line);
+    assert_eq!(run(&opts(&args), &queried).unwrap().full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|61/151W|FAN 33%");
+    // No fan or power reading on this one, so they're just left out
+    let line = format!("37, 1436, 8119, 58, [N/A], 151.00, [N/A]{}", ", Not Active".repeat(7));
+    let queried = runner(STATUS).with_stdout(&query(fields), &line);
+    let block = run(&opts(&args), &queried).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|LIM 151W");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|MEM 18|LIM 151W"));
+    assert_eq!(queried.calls().len(), 1);
+    // Same out of -q
+    let status = STATUS
+        .replace("Fan Speed                       : 33 %", "Fan Speed                       : N/A")
+        .replace("Power Draw                  : 61.22 W", "Power Draw                  : N/A");
+    assert_eq!(run(&opts(&args), &runner(&status)).unwrap().full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|LIM 151W");
+    // A rig of passively cooled cards
+    let line = format!("60, 1000, 8000, 70, [N/A]{}", ", Not Active".repeat(7));
+    let all = query("utilization.gpu,memory.used,memory.total,temperature.gpu,fan.speed").replace(" -i 0", "");
+    let passive = FixtureRunner::new().with_stdout(&all, &format!("{}\n{}", line, line));
+    let block = run(&opts(&["--summary", "--fan"]), &passive).unwrap();
+    assert_eq!(block.full_text, "2 GPUs: GPU 60%|2000/16000MiB|70C");
+}
+
+#[test]
+fn power_newer_drivers() {
+    // 530 and up split the draw and rename the limit
+    let status = STATUS
+        .replace("        Power Draw                  : 61.22 W", "        Average Power Draw          : 58.10 W\n        Instantaneous Power Draw    : 61.22 W")
+        .replace("        Power Limit                 : 151.00 W", "        Current Power Limit         : 140.00 W\n        Requested Power Limit       : 140.00 W");
+    let block = run(&opts(&["--power-draw", "--power-limit"]), &runner(&status)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C|58/140W");
+}
+
+#[test]
+fn clocks_and_pstate() {
+    let block = run(&opts(&["--no-mem", "--no-temp", "--clocks", "--pstate"]), &runner(STATUS)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|GFX 1708/1911MHz|SM 1708/1911MHz|VRAM 3802/4004MHz|P2");
+    assert_eq!(block.short_text.as_deref(), Some("GPU 0: GPU 37|P2"));
+
+    let fields = "clocks.gr,clocks.sm,clocks.mem,clocks.max.gr,clocks.max.sm,clocks.max.mem,pstate";
+    // Stuck idling after a driver hiccup
+    let line = format!("139, 139, 405, 1911, 1911, 4004, P8{}", ", Not Active".repeat(7));
+    let runner = FixtureRunner::new().with_stdout(&query(fields), &line);
+    let block = run(&opts(&["--no-util", "--no-mem", "--no-temp", "--clocks", "--pstate"]), &runner).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GFX 139/1911MHz|SM 139/1911MHz|VRAM 405/4004MHz|P8");
+
+    let stats = GpuStats::from_csv(&line, &[Field::Clocks, Field::PState, Field::Throttle]).unwrap();
+    assert_eq!(stats.clocks, Some(Clocks { graphics: 139.0, sm: 139.0, memory: 405.0 }));
+    assert_eq!(stats.max_clocks, GpuStats::from_status(STATUS, &[Field::Clocks]).unwrap().max_clocks);
+}
+
+// Cards that don't report throttle reasons at all
+const QUERY_NO_THROTTLE: &str = include_str!("fixtures/nvidia-smi-query-gpu-no-throttle.csv");
+
+#[test]
+fn throttle_not_supported() {
+    let fields = "utilization.gpu,memory.used,memory.total,temperature.gpu";
+    let queried = runner(STATUS).with_stdout(&query(fields), QUERY_NO_THROTTLE);
+    let block = run(&opts(&["--throttle-reason"]), &queried).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C");
+    assert_eq!(block.color, None);
+    // Straight from the query, no -q
+    assert_eq!(queried.calls().len(), 1);
+
+    // The -q version has no reasons under it at all
+    let start = STATUS.find("    Clocks Throttle Reasons").unwrap();
+    let end = STATUS.find("    FB Memory Usage").unwrap();
+    let status = format!("{}    Clocks Throttle Reasons         : N/A\n{}", &STATUS[..start], &STATUS[end..]);
+    let block = run(&opts(&["--throttle-reason"]), &runner(&status)).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C");
+    assert_eq!(block.color, None);
+}
+
+#[test]
+fn throttle_colors_off() {
+    // Nothing left to show idle or throttling with, so they aren't asked for
+    let args = ["--color-idle", "", "--color-throttle", "", "--color-hw-slowdown", "", "--color-thermal", ""];
+    let fields = "utilization.gpu,memory.used,memory.total,temperature.gpu";
+    let line = format!("nvidia-smi --query-gpu={} --format=csv,noheader,nounits -i 0", fields);
+    let block = run(&opts(&args), &FixtureRunner::new().with_stdout(&line, "37, 1436, 8119, 58")).unwrap();
+    assert_eq!(block.full_text, "GPU 0: GPU 37%|1436/8119MiB|58C");
+    assert_eq!(block.color, None);
+    // Only blanking idle still leaves throttling
+    let status = throttling(&["Idle", "SW Thermal Slowdown"]);
+    assert_eq!(run(&opts(&["--color-idle", ""]), &runner(&status)).unwrap().color.as_deref(), Some("#cb4b16"));
+    // And blanking one reason's color just leaves that one uncolored
+    let status = throttling(&["SW Thermal Slowdown"]);
+    assert_eq!(run(&opts(&["--color-thermal", ""]), &runner(&status)).unwrap().color, None);
+}
@@ -0,0 +1,687 @@
+use clap::Clap;
+use quickshell::audio::{Card, Profile, Target, Volume};
+use quickshell::pactl::{parse_cards, parse_devices, parse_json_cards, parse_json_devices};
+use quickshell::pipewire::{parse_pw_cards, parse_pw_default, parse_pw_dump, parse_pw_route};
+use quickshell::pulse::{
+    balance, find_device, find_streams, is_device_event, meter_bar, meter_source, next_device, peak, recording_apps, run,
+    Opts,
+};
+use quickshell::{ClickEvent, FixtureRunner, WidgetError};
+
+const ANALOG: &str = "alsa_output.pci-0000_00_1f.3.analog-stereo";
+
+fn opts(args: &[&str]) -> Opts {
+    Opts::parse_from(std::iter::once(&"pulse-status").chain(args))
+}
+
+fn runner() -> FixtureRunner {
+    FixtureRunner::new()
+        .with_stdout("pactl info", include_str!("fixtures/pactl-info.txt"))
+        .with_stdout("pactl list short sinks", include_str!("fixtures/pactl-list-short-sinks.txt"))
+        .with_stdout("pactl list sinks", include_str!("fixtures/pactl-list-sinks.txt"))
+        .with_stdout("pactl list short sources", include_str!("fixtures/pactl-list-short-sources.txt"))
+        .with_stdout("pactl list sources", include_str!("fixtures/pactl-list-sources.txt"))
+        .with_stdout("pactl list short sink-inputs", include_str!("fixtures/pactl-list-short-sink-inputs.txt"))
+        .with_stdout("pactl list sink-inputs", include_str!("fixtures/pactl-list-sink-inputs.txt"))
+        .with_stdout("pactl list cards", include_str!("fixtures/pactl-list-cards.txt"))
+}
+
+/// pactl 16+
+fn runner_json() -> FixtureRunner {
+    FixtureRunner::new()
+        .with_stdout("pactl --format=json info", include_str!("fixtures/pactl-json-info.json"))
+        .with_stdout("pactl --format=json list sinks", include_str!("fixtures/pactl-json-list-sinks.json"))
+        .with_stdout("pactl --format=json list sources", include_str!("fixtures/pactl-json-list-sources.json"))
+        .with_stdout("pactl --format=json list cards", include_str!("fixtures/pactl-json-list-cards.json"))
+        .with_stdout("pactl --format=json list source-outputs", "[]")
+}
+
+#[test]
+fn default_sink() {
+    let block = run(&opts(&[]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "🔈 65% / -11.23 dB");
+    assert_eq!(block.short_text.as_deref(), Some("🔈 65%"));
+    assert_eq!(block.color.as_deref(), Some("#268bd2"));
+}
+
+#[test]
+fn default_source_muted() {
+    let block = run(&opts(&["--source", "--background-mute", "#dc322f"]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "🎙️🔇 120% / 4.75 dB");
+    assert_eq!(block.color.as_deref(), Some("#859900"));
+    assert_eq!(block.background.as_deref(), Some("#dc322f"));
+}
+
+#[test]
+fn numeric_id() {
+    let block = run(&opts(&["--id", "0"]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "🔉 100% / 0.00 dB");
+    assert_eq!(block.color, None);
+}
+
+#[test]
+fn numeric_id_missing() {
+    assert_eq!(
+        run(&opts(&["--id", "7"]), None, &runner()),
+        Err(WidgetError::DeviceNotFound(String::from("7")))
+    );
+}
+
+fn runner_pw() -> FixtureRunner {
+    FixtureRunner::new().with_stdout("pw-dump", include_str!("fixtures/pw-dump.json"))
+}
+
+#[test]
+fn parse_pw_nodes() {
+    let dump = include_str!("fixtures/pw-dump.json");
+    let sinks = parse_pw_dump(dump, Target::Sinks).unwrap();
+    let names: Vec<_> = sinks.iter().map(|dev| (dev.index, dev.name.as_str())).collect();
+    assert_eq!(names, vec![(48, ANALOG), (50, "alsa_output.pci-0000_01_00.1.hdmi-stereo")]);
+    assert_eq!(sinks[0].nick(), Some("ALC1220 Analog"));
+    assert_eq!(sinks[0].volume, parse_devices(include_str!("fixtures/pactl-list-sinks.txt"))[1].volume);
+    // the webcam isn't audio
+    let sources = parse_pw_dump(dump, Target::Sources).unwrap();
+    assert_eq!(sources.len(), 1);
+    assert!(sources[0].mute);
+    assert_eq!(sources[0].properties.get("alsa.card").map(String::as_str), Some("2"));
+    assert_eq!(parse_pw_default(dump, false).as_deref(), Some(ANALOG));
+}
+
+#[test]
+fn pipewire_auto() {
+    let runner = runner_pw();
+    let block = run(&opts(&["--source"]), None, &runner).unwrap();
+    // Firefox is recording from it
+    assert_eq!(block.full_text, "🔴🎙️🔇 120% / 4.75 dB");
+    // the probe's dump gets reused
+    assert_eq!(runner.calls(), vec!["pw-dump"]);
+}
+
+#[test]
+fn pipewire_clicks() {
+    let runner = runner_pw();
+    let opts = opts(&["--backend", "pipewire", "--id", "ultragear"]);
+    run(&opts, Some(ClickEvent::button(1)), &runner).unwrap();
+    run(&opts, Some(ClickEvent::button(4)), &runner).unwrap();
+    let wpctl: Vec<_> = runner.calls().into_iter().filter(|call| call.starts_with("wpctl")).collect();
+    assert_eq!(wpctl, vec!["wpctl set-mute 50 toggle", "wpctl set-volume 50 1.0391"]);
+}
+
+#[test]
+fn forced_pulse() {
+    let runner = runner().with_stdout("pw-dump", include_str!("fixtures/pw-dump.json"));
+    run(&opts(&["--backend", "pulse"]), None, &runner).unwrap();
+    assert!(!runner.calls().contains(&String::from("pw-dump")));
+}
+
+#[test]
+fn parse_sources() {
+    let devices = parse_devices(include_str!("fixtures/pactl-list-sources.txt"));
+    let names: Vec<_> = devices.iter().map(|dev| (dev.index, dev.name.as_str())).collect();
+    assert_eq!(
+        names,
+        vec![
+            (0, "alsa_output.pci-0000_01_00.1.hdmi-stereo.monitor"),
+            (1, "alsa_output.pci-0000_00_1f.3.analog-stereo.monitor"),
+            (2, "alsa_input.usb-Blue_Microphones_Yeti_Stereo_Microphone_REV8-00.analog-stereo"),
+        ]
+    );
+    assert_eq!(devices[2].description, "Yeti Stereo Microphone Analog Stereo");
+    assert!(devices[2].mute);
+    assert_eq!(devices[2].volume[1].channel, "front-right");
+    assert_eq!(devices[2].volume[1].percent, 120);
+    assert_eq!(devices[2].volume[1].db, "4.75 dB");
+    assert_eq!(devices[2].monitor_of, None);
+    assert_eq!(devices[0].monitor_of.as_deref(), Some("alsa_output.pci-0000_01_00.1.hdmi-stereo"));
+    assert_eq!(devices[1].properties.get("device.class").map(String::as_str), Some("monitor"));
+}
+
+#[test]
+fn parse_sinks_ports() {
+    let devices = parse_devices(include_str!("fixtures/pactl-list-sinks.txt"));
+    let ports: Vec<_> = devices[1].ports.iter().map(|port| (port.name.as_str(), port.available)).collect();
+    assert_eq!(ports, vec![("analog-output-lineout", false), ("analog-output-headphones", true)]);
+    assert_eq!(devices[1].ports[1].port_type, "Headphones");
+    assert_eq!(devices[1].active_port.as_deref(), Some("analog-output-headphones"));
+}
+
+#[test]
+fn json_matches_text() {
+    for (json, text) in [
+        (include_str!("fixtures/pactl-json-list-sinks.json"), include_str!("fixtures/pactl-list-sinks.txt")),
+        (include_str!("fixtures/pactl-json-list-sources.json"), include_str!("fixtures/pactl-list-sources.txt")),
+        (
+            include_str!("fixtures/pactl-json-list-sink-inputs.json"),
+            include_str!("fixtures/pactl-list-sink-inputs.txt"),
+        ),
+        (
+            include_str!("fixtures/pactl-json-list-source-outputs.json"),
+            include_str!("fixtures/pactl-list-source-outputs.txt"),
+        ),
+    ] {
+        assert_eq!(parse_json_devices(json).unwrap(), parse_devices(text));
+    }
+    assert_eq!(parse_json_devices("Sink #0"), None);
+}
+
+#[test]
+fn json_default_source() {
+    let runner = runner_json();
+    let block = run(&opts(&["--source"]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "🎙️🔇 120% / 4.75 dB");
+    // never needed the text output
+    assert!(runner.calls().iter().all(|call| !call.starts_with("pactl list") && call != "pactl info"));
+}
+
+#[test]
+fn find_by_name_description_regex() {
+    let devices = parse_devices(include_str!("fixtures/pactl-list-sources.txt"));
+    let yeti = "alsa_input.usb-Blue_Microphones_Yeti_Stereo_Microphone_REV8-00.analog-stereo";
+    assert_eq!(find_device(&devices, yeti).unwrap().index, 2);
+    assert_eq!(find_device(&devices, "yeti stereo").unwrap().index, 2);
+    assert_eq!(find_device(&devices, "^alsa_input").unwrap().index, 2);
+    // exact name wins over its own .monitor
+    assert_eq!(find_device(&devices, &format!("{}.monitor", ANALOG)).unwrap().index, 1);
+    assert_eq!(
+        find_device(&devices, "webcam"),
+        Err(WidgetError::DeviceNotFound(String::from("webcam")))
+    );
+}
+
+#[test]
+fn find_ambiguous() {
+    let devices = parse_devices(include_str!("fixtures/pactl-list-sources.txt"));
+    assert_eq!(
+        find_device(&devices, "monitor of"),
+        Err(WidgetError::AmbiguousDevice {
+            id: String::from("monitor of"),
+            candidates: vec![
+                String::from("alsa_output.pci-0000_01_00.1.hdmi-stereo.monitor"),
+                format!("{}.monitor", ANALOG),
+            ],
+        })
+    );
+}
+
+#[test]
+fn description_id() {
+    let runner = runner();
+    let block = run(&opts(&["--id", "hdmi"]), Some(ClickEvent::button(1)), &runner).unwrap();
+    assert_eq!(block.full_text, "🔉 100% / 0.00 dB");
+    assert!(runner.calls().contains(&String::from("pactl set-sink-mute alsa_output.pci-0000_01_00.1.hdmi-stereo toggle")));
+}
+
+#[test]
+fn next_sink() {
+    let sinks = parse_devices(include_str!("fixtures/pactl-list-sinks.txt"));
+    let hdmi = "alsa_output.pci-0000_01_00.1.hdmi-stereo";
+    assert_eq!(next_device(&sinks, ANALOG, &[]).map(|dev| dev.name.as_str()), Some(hdmi));
+    assert_eq!(next_device(&sinks, hdmi, &[]).map(|dev| dev.name.as_str()), Some(ANALOG));
+    assert_eq!(next_device(&sinks, ANALOG, &[String::from("HDMI"), String::from("(")]), None);
+    // the default went away, start from the top
+    assert_eq!(next_device(&sinks[..1], ANALOG, &[]).map(|dev| dev.name.as_str()), Some(hdmi));
+}
+
+#[test]
+fn next_source_skips_monitors() {
+    let sources = parse_devices(include_str!("fixtures/pactl-list-sources.txt"));
+    let yeti = &sources[2].name;
+    assert_eq!(next_device(&sources, yeti, &[]), None);
+}
+
+#[test]
+fn click_cycle() {
+    let hdmi = "alsa_output.pci-0000_01_00.1.hdmi-stereo";
+    let runner = runner().with_stdout(&format!("pactl set-default-sink {}", hdmi), "");
+    let block = run(&opts(&[]), Some(ClickEvent::button(2)), &runner).unwrap();
+    assert!(runner.calls().contains(&format!("pactl set-default-sink {}", hdmi)));
+    assert!(runner.calls().contains(&format!("pactl move-sink-input 34 {}", hdmi)));
+    assert!(runner.calls().contains(&format!("pactl move-sink-input 41 {}", hdmi)));
+    // shows the new default, since there's no --id
+    assert_eq!(block.full_text, "🔉 100% / 0.00 dB");
+}
+
+#[test]
+fn click_cycle_excluded() {
+    let runner = runner();
+    let opts = opts(&["--exclude", "hdmi", "2"]);
+    // --exclude takes one value at a time, so the button still gets through
+    assert_eq!(opts.button, Some(2));
+    run(&opts, ClickEvent::get(opts.button), &runner).unwrap();
+    assert!(runner.calls().iter().all(|call| !call.starts_with("pactl set-default")));
+}
+
+#[test]
+fn pipewire_cycle() {
+    let runner = runner_pw().with_stdout("wpctl set-default 50", "");
+    run(&opts(&["--backend", "pipewire"]), Some(ClickEvent::button(2)), &runner).unwrap();
+    let calls: Vec<_> = runner.calls().into_iter().filter(|call| !call.starts_with("pw-dump")).collect();
+    assert_eq!(
+        calls,
+        vec!["wpctl set-default 50", "pw-metadata -d 80 target.object", "pw-metadata -d 80 target.node"]
+    );
+}
+
+#[test]
+fn click_mute() {
+    let runner = runner();
+    run(&opts(&[]), Some(ClickEvent::button(1)), &runner).unwrap();
+    assert!(runner.calls().contains(&format!("pactl set-sink-mute {} toggle", ANALOG)));
+}
+
+#[test]
+fn click_scroll() {
+    let runner = runner();
+    run(&opts(&[]), Some(ClickEvent::button(5)), &runner).unwrap();
+    assert!(runner.calls().contains(&format!("pactl set-sink-volume {} 62.55%", ANALOG)));
+}
+
+#[test]
+fn volume_units() {
+    assert_eq!("5%".parse(), Ok(Volume::Percent(5.0)));
+    assert_eq!("5".parse(), Ok(Volume::Percent(5.0)));
+    assert_eq!("2dB".parse(), Ok(Volume::Db(2.0)));
+    assert_eq!("-6 db".parse(), Ok(Volume::Db(-6.0)));
+    assert!("loud".parse::<Volume>().is_err());
+    assert_eq!(Volume::Db(-11.23).percent().round(), 65.0);
+    assert_eq!(Volume::Percent(5.0).step(98.0, true), 103.0);
+    assert_eq!(Volume::Percent(5.0).step(3.0, false), 0.0);
+    // from silence dB steps need somewhere to start
+    assert!(Volume::Db(1.0).step(0.0, true) > 1.0);
+}
+
+#[test]
+fn step_percent() {
+    let runner = runner();
+    let opts = opts(&["--step", "5%"]);
+    run(&opts, Some(ClickEvent::button(4)), &runner).unwrap();
+    let mut shift = ClickEvent::button(4);
+    shift.modifiers = vec![String::from("Shift")];
+    run(&opts, Some(shift), &runner).unwrap();
+    assert!(runner.calls().contains(&format!("pactl set-sink-volume {} 70%", ANALOG)));
+    assert!(runner.calls().contains(&format!("pactl set-sink-volume {} 90%", ANALOG)));
+}
+
+#[test]
+fn max_volume_caps() {
+    let runner = runner();
+    let opts = opts(&["--step", "10%", "--max-volume", "70%", "--reset-to", "0dB"]);
+    run(&opts, Some(ClickEvent::button(4)), &runner).unwrap();
+    run(&opts, Some(ClickEvent::button(3)), &runner).unwrap();
+    let volume: Vec<_> = runner.calls().into_iter().filter(|call| call.contains("volume")).collect();
+    assert_eq!(
+        volume,
+        vec![format!("pactl set-sink-volume {} 70%", ANALOG), format!("pactl set-sink-volume {} 70%", ANALOG)]
+    );
+}
+
+#[test]
+fn max_volume_already_over() {
+    // The Yeti is at 120% from somewhere else. Can't go up, can go down.
+    let runner = runner();
+    let yeti = "alsa_input.usb-Blue_Microphones_Yeti_Stereo_Microphone_REV8-00.analog-stereo";
+    let opts = opts(&["--source", "--step", "10%", "--max-volume", "100"]);
+    run(&opts, Some(ClickEvent::button(4)), &runner).unwrap();
+    run(&opts, Some(ClickEvent::button(5)), &runner).unwrap();
+    let volume: Vec<_> = runner.calls().into_iter().filter(|call| call.contains("volume")).collect();
+    assert_eq!(
+        volume,
+        vec![format!("pactl set-source-volume {} 120%", yeti), format!("pactl set-source-volume {} 110%", yeti)]
+    );
+}
+
+#[test]
+fn subscribe_events() {
+    let sinks = Target::Sinks;
+    assert!(is_device_event("Event 'change' on sink #1", sinks));
+    assert!(is_device_event("Event 'new' on source #4", sinks));
+    assert!(is_device_event("Event 'change' on server", sinks));
+    assert!(is_device_event("Event 'remove' on sink #0", sinks));
+    assert!(!is_device_event("Event 'change' on sink-input #52", sinks));
+    assert!(!is_device_event("Event 'new' on client #113", sinks));
+    assert!(!is_device_event("Event 'change' on card #0", sinks));
+    assert!(is_device_event("Event 'change' on sink-input #52", Target::SinkInputs));
+    assert!(is_device_event("Event 'remove' on source-output #9", Target::SourceOutputs));
+    assert!(is_device_event("Event 'new' on source-output #9", Target::Sources));
+    assert!(!is_device_event("Event 'new' on source-output #9", sinks));
+}
+
+#[test]
+fn streams_by_app_or_binary() {
+    let streams = parse_devices(include_str!("fixtures/pactl-list-sink-inputs.txt"));
+    let ids = |app| find_streams(&streams, app).iter().map(|dev| dev.index).collect::<Vec<_>>();
+    assert_eq!(ids("firefox"), vec![34, 41]);
+    assert_eq!(ids("Spotify"), vec![45]);
+    assert_eq!(ids("mpv"), Vec::<u32>::new());
+    assert_eq!(streams[0].name, "Firefox");
+}
+
+#[test]
+fn stream_block() {
+    let block = run(&opts(&["--stream", "spotify"]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "🔈 55% / -15.58 dB");
+    assert_eq!(block.color.as_deref(), Some("#268bd2"));
+}
+
+#[test]
+fn stream_clicks_every_stream() {
+    let runner = runner();
+    run(&opts(&["--stream", "firefox"]), Some(ClickEvent::button(5)), &runner).unwrap();
+    let volume: Vec<_> = runner.calls().into_iter().filter(|call| call.contains("volume")).collect();
+    assert_eq!(volume, vec!["pactl set-sink-input-volume 34 76.99%", "pactl set-sink-input-volume 41 96.24%"]);
+    // nothing touched the sink
+    assert!(runner.calls().iter().all(|call| !call.contains(ANALOG)));
+}
+
+#[test]
+fn stream_not_playing() {
+    let runner = runner();
+    let block = run(&opts(&["--stream", "mpv"]), Some(ClickEvent::button(1)), &runner).unwrap();
+    assert_eq!(block.full_text, "");
+    assert!(runner.calls().iter().all(|call| !call.contains("mute")));
+}
+
+#[test]
+fn pipewire_stream() {
+    let runner = runner_pw();
+    let opts = opts(&["--backend", "pipewire", "--stream", "firefox"]);
+    let block = run(&opts, Some(ClickEvent::button(1)), &runner).unwrap();
+    assert_eq!(block.full_text, "🔉 100% / 0.00 dB");
+    assert!(runner.calls().contains(&String::from("wpctl set-mute 80 toggle")));
+}
+
+#[test]
+fn port_icon() {
+    let block = run(&opts(&["--show-port"]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "🎧🔈 65% / -11.23 dB");
+    // Ports without an icon show their name
+    let block = run(&opts(&["--show-port", "--port-icons", "line:🔌"]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "Headphones🔈 65% / -11.23 dB");
+}
+
+#[test]
+fn port_cycle_skips_unplugged() {
+    let runner = runner();
+    let mut click = ClickEvent::button(1);
+    click.modifiers = vec![String::from("Shift")];
+    run(&opts(&[]), Some(click), &runner).unwrap();
+    // Line out is unplugged, and headphones are already active
+    assert!(runner.calls().iter().all(|call| !call.starts_with("pactl set-sink-port")));
+    assert!(runner.calls().iter().all(|call| !call.starts_with("pactl set-sink-mute")));
+}
+
+#[test]
+fn port_cycle() {
+    let sinks = include_str!("fixtures/pactl-list-sinks.txt").replace("9000, not available", "9000, available");
+    let runner = runner()
+        .with_stdout("pactl list sinks", &sinks)
+        .with_stdout(&format!("pactl set-sink-port {} analog-output-lineout", ANALOG), "");
+    let opts = opts(&["--port-click", "ctrl+2"]);
+    let mut click = ClickEvent::button(2);
+    click.modifiers = vec![String::from("Control")];
+    run(&opts, Some(click), &runner).unwrap();
+    assert!(runner.calls().contains(&format!("pactl set-sink-port {} analog-output-lineout", ANALOG)));
+    assert!(runner.calls().iter().all(|call| !call.starts_with("pactl set-default")));
+}
+
+#[test]
+fn parse_pw_ports() {
+    let dump = include_str!("fixtures/pw-dump.json");
+    let sinks = parse_pw_dump(dump, Target::Sinks).unwrap();
+    // S/PDIF is on another profile
+    let ports: Vec<_> = sinks[0].ports.iter().map(|port| (port.name.as_str(), port.available)).collect();
+    assert_eq!(
+        ports,
+        vec![("analog-output-speaker", true), ("analog-output-headphones", true), ("analog-output-lineout", false)]
+    );
+    assert_eq!(sinks[0].ports[1].kind(), "headphones");
+    assert_eq!(sinks[0].active_port.as_deref(), Some("analog-output-speaker"));
+    // The HDMI card and the Yeti have no routes in the dump
+    assert!(sinks[1].ports.is_empty());
+    assert_eq!(parse_pw_route(dump, 48, "analog-output-headphones", Target::Sinks), Some((42, 2, 3)));
+    assert_eq!(parse_pw_route(dump, 48, "analog-input-internal-mic", Target::Sinks), None);
+}
+
+#[test]
+fn pipewire_ports() {
+    let runner = runner_pw();
+    let block = run(&opts(&["--backend", "pipewire", "--show-port"]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "📢🔈 65% / -11.23 dB");
+    let mut click = ClickEvent::button(1);
+    click.modifiers = vec![String::from("Shift")];
+    run(&opts(&["--backend", "pipewire"]), Some(click), &runner).unwrap();
+    assert!(runner.calls().contains(&String::from("pw-cli set-param 42 Route { index: 2, device: 3, save: true }")));
+    assert!(runner.calls().iter().all(|call| !call.starts_with("wpctl set-mute")));
+}
+
+#[test]
+fn parse_cards_json_matches_text() {
+    let cards = parse_cards(include_str!("fixtures/pactl-list-cards.txt"));
+    assert_eq!(cards.len(), 3);
+    assert_eq!(cards[0].name, "alsa_card.pci-0000_00_1f.3");
+    assert_eq!(cards[0].active_profile.as_deref(), Some("output:analog-stereo+input:analog-stereo"));
+    assert_eq!(cards[0].profiles.len(), 5);
+    // Digital Stereo (IEC958) has parens of its own
+    assert_eq!(cards[0].profiles[2].description, "Digital Stereo (IEC958) Output + Analog Stereo Input");
+    assert!(!cards[0].profiles[2].available);
+    // Port properties aren't the card's
+    assert_eq!(cards[0].properties.get("port.type"), None);
+    assert_eq!(parse_json_cards(include_str!("fixtures/pactl-json-list-cards.json")), Some(cards));
+}
+
+#[test]
+fn cards_own_devices() {
+    let cards = parse_cards(include_str!("fixtures/pactl-list-cards.txt"));
+    let sinks = parse_devices(include_str!("fixtures/pactl-list-sinks.txt"));
+    let sources = parse_devices(include_str!("fixtures/pactl-list-sources.txt"));
+    let owner = |dev| cards.iter().find(|card| card.owns(dev)).map(|card| card.index);
+    assert_eq!(owner(&sinks[0]), Some(1));
+    assert_eq!(owner(&sinks[1]), Some(0));
+    // Monitors only have alsa.card
+    assert_eq!(owner(&sources[0]), Some(1));
+    assert_eq!(owner(&sources[2]), Some(2));
+
+    let dump = include_str!("fixtures/pw-dump.json");
+    let cards = parse_pw_cards(dump).unwrap();
+    assert_eq!(cards.len(), 1);
+    assert_eq!(cards[0].profile().map(|p| p.index), Some(1));
+    let sinks = parse_pw_dump(dump, Target::Sinks).unwrap();
+    assert!(cards[0].owns(&sinks[0]));
+    assert!(!cards[0].owns(&sinks[1]));
+}
+
+#[test]
+fn next_profile() {
+    let profile = |index, name: &str| Profile {
+        index,
+        name: name.to_string(),
+        description: String::new(),
+        available: true,
+    };
+    let mut card = Card {
+        index: 3,
+        name: String::from("bluez_card.00_1B_66_AA_BB_CC"),
+        profiles: vec![profile(0, "a2dp_sink"), profile(1, "headset_head_unit"), profile(2, "off")],
+        active_profile: Some(String::from("a2dp_sink")),
+        properties: Default::default(),
+    };
+    // PipeWire's spelling works on pulse too
+    let wanted = ["a2dp-sink", "headset-head-unit"];
+    assert_eq!(card.next_profile(&wanted).map(|p| p.index), Some(1));
+    card.active_profile = Some(String::from("headset_head_unit"));
+    assert_eq!(card.next_profile(&wanted).map(|p| p.index), Some(0));
+    // Starts from the top when on something else
+    card.active_profile = Some(String::from("off"));
+    assert_eq!(card.next_profile(&wanted).map(|p| p.index), Some(0));
+    card.profiles[1].available = false;
+    card.active_profile = Some(String::from("a2dp_sink"));
+    assert_eq!(card.next_profile(&wanted), None);
+}
+
+#[test]
+fn show_profile() {
+    let block = run(&opts(&["--show-profile"]), None, &runner()).unwrap();
+    assert_eq!(block.full_text, "🔈 65% / -11.23 dB [Analog Stereo Duplex]");
+    let block = run(&opts(&["--show-profile", "--backend", "pipewire"]), None, &runner_pw()).unwrap();
+    assert_eq!(block.full_text, "🔈 65% / -11.23 dB [Analog Stereo Duplex]");
+}
+
+#[test]
+fn profile_cycle() {
+    let switch = "pactl set-card-profile alsa_card.pci-0000_00_1f.3 output:analog-stereo";
+    let runner = runner().with_stdout(switch, "");
+    let pulse = opts(&["--profiles", "output:analog-stereo+input:analog-stereo, output:analog-stereo"]);
+    let mut click = ClickEvent::button(1);
+    click.modifiers = vec![String::from("Control")];
+    run(&pulse, Some(click.clone()), &runner).unwrap();
+    assert!(runner.calls().contains(&switch.to_string()));
+    assert!(runner.calls().iter().all(|call| !call.starts_with("pactl set-sink-mute")));
+
+    let runner = runner_pw().with_stdout("wpctl set-profile 42 2", "");
+    let pipewire = opts(&["--backend", "pipewire", "--profiles", "output:analog-stereo"]);
+    run(&pipewire, Some(click), &runner).unwrap();
+    assert!(runner.calls().contains(&String::from("wpctl set-profile 42 2")));
+}
+
+#[test]
+fn recording_apps_on_source() {
+    let sources = parse_devices(include_str!("fixtures/pactl-list-sources.txt"));
+    let streams = parse_devices(include_str!("fixtures/pactl-list-source-outputs.txt"));
+    assert_eq!(streams[0].connected_to, Some(2));
+    let ignore = vec![String::from("pavucontrol")];
+    assert_eq!(recording_apps(&streams, &sources[2], &ignore), vec!["Firefox"]);
+    assert_eq!(recording_apps(&streams, &sources[2], &[]), vec!["Firefox", "PulseAudio Volume Control"]);
+    // OBS records the analog monitor, which doesn't count
+    assert_eq!(recording_apps(&streams, &sources[1], &[]), Vec::<String>::new());
+
+    let dump = include_str!("fixtures/pw-dump.json");
+    assert_eq!(parse_pw_dump(dump, Target::SourceOutputs).unwrap()[0].connected_to, Some(55));
+    assert_eq!(parse_pw_dump(dump, Target::SinkInputs).unwrap()[0].connected_to, Some(48));
+}
+
+#[test]
+fn recording_block() {
+    let sources = include_str!("fixtures/pactl-list-sources.txt").replace("Mute: yes", "Mute: no");
+    let runner = runner()
+        .with_stdout("pactl list sources", &sources)
+        .with_stdout("pactl list source-outputs", include_str!("fixtures/pactl-list-source-outputs.txt"));
+    let block = run(&opts(&["--source", "--show-recording"]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "🔴🎙️🔊 120% / 4.75 dB (Firefox)");
+    assert_eq!(block.short_text.as_deref(), Some("🔴🎙️🔊 120%"));
+    assert_eq!(block.color.as_deref(), Some("#dc322f"));
+
+    // Only pavucontrol's meter left
+    let ignore = opts(&["--source", "--ignore-recording", "firefox", "--ignore-recording", "pavucontrol"]);
+    let block = run(&ignore, None, &runner).unwrap();
+    assert_eq!(block.full_text, "🎙️🔊 120% / 4.75 dB");
+    assert_eq!(block.color.as_deref(), Some("#b58900"));
+
+    // Muted still says something has the mic open
+    let block = run(&opts(&["--source", "--backend", "pipewire"]), None, &runner_pw()).unwrap();
+    assert_eq!(block.full_text, "🔴🎙️🔇 120% / 4.75 dB");
+    assert_eq!(block.color.as_deref(), Some("#859900"));
+}
+
+/// The analog sink with its right side turned down to `right`.
+fn runner_unbalanced(right: &str) -> FixtureRunner {
+    let sinks = include_str!("fixtures/pactl-list-sinks.txt")
+        .replace("front-right: 42597 /  65% / -11.23 dB", &format!("front-right: {}", right));
+    runner().with_stdout("pactl list sinks", &sinks)
+}
+
+#[test]
+fn channel_balance() {
+    let sinks = parse_devices(include_str!("fixtures/pactl-list-sinks.txt"));
+    assert_eq!(sinks[1].balance(), 0.0);
+    assert_eq!(balance(0.0), "C");
+    assert_eq!(balance(-0.2308), "L23%");
+    assert_eq!(balance(0.05), "R5%");
+
+    let mut dev = sinks[1].clone();
+    dev.volume[1].percent = 50;
+    assert!((dev.balance() + 0.2308).abs() < 0.001);
+    assert_eq!(dev.balanced(0.0), vec![65.0, 65.0]);
+    assert_eq!(dev.balanced(0.5), vec![32.5, 65.0]);
+    assert_eq!(dev.balanced(-2.0), vec![65.0, 0.0]);
+    assert_eq!(dev.scaled(130.0), vec![130.0, 100.0]);
+}
+
+#[test]
+fn loudest_channel_shown() {
+    let runner = runner_unbalanced("52429 /  80% / -5.81 dB");
+    let block = run(&opts(&["--balance"]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "🔈 80% / -5.81 dB R19%");
+    let block = run(&opts(&["--balance", "--id", "0"]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "🔉 100% / 0.00 dB C");
+}
+
+#[test]
+fn balance_scroll() {
+    let runner = runner_unbalanced("32768 /  50% / -18.06 dB");
+    let mut ctrl = ClickEvent::button(4);
+    ctrl.modifiers = vec![String::from("Control")];
+    run(&opts(&[]), Some(ctrl), &runner).unwrap();
+    // Plain scrolling keeps the balance
+    run(&opts(&[]), Some(ClickEvent::button(5)), &runner).unwrap();
+    let volume: Vec<_> = runner.calls().into_iter().filter(|call| call.contains("volume")).collect();
+    assert_eq!(
+        volume,
+        vec![
+            format!("pactl set-sink-volume {} 65% 53.25%", ANALOG),
+            format!("pactl set-sink-volume {} 62.55% 48.12%", ANALOG),
+        ]
+    );
+
+    let runner = runner_pw();
+    let mut ctrl = ClickEvent::button(5);
+    ctrl.modifiers = vec![String::from("Control")];
+    run(&opts(&["--backend", "pipewire"]), Some(ctrl), &runner).unwrap();
+    assert!(runner
+        .calls()
+        .contains(&String::from("pw-cli set-param 48 Props { channelVolumes: [ 0.274625, 0.235457 ] }")));
+}
+
+#[test]
+fn meter_peaks() {
+    let samples: Vec<u8> = [0i16, -16384, 8192, 0].iter().flat_map(|sample| sample.to_le_bytes().to_vec()).collect();
+    assert_eq!(peak(&samples), 0.5);
+    assert_eq!(peak(&[]), 0.0);
+    assert_eq!(meter_bar(0.0, 5), "     ");
+    assert_eq!(meter_bar(1.0, 5), "█████");
+    // -6 dB is 9/10 of the way on a 60 dB scale
+    assert_eq!(meter_bar(0.5, 5), "████▌");
+    assert_eq!(meter_bar(0.001, 3), "   ");
+}
+
+#[test]
+fn meter_listens_to_monitor() {
+    let runner = runner();
+    assert_eq!(meter_source(&opts(&["--meter"]), &runner), Ok(String::from("@DEFAULT_MONITOR@")));
+    assert_eq!(meter_source(&opts(&["--meter", "--source"]), &runner), Ok(String::from("@DEFAULT_SOURCE@")));
+    assert_eq!(
+        meter_source(&opts(&["--meter", "--id", "0"]), &runner),
+        Ok(String::from("alsa_output.pci-0000_01_00.1.hdmi-stereo.monitor"))
+    );
+    assert_eq!(
+        meter_source(&opts(&["--meter", "--source", "--id", "yeti"]), &runner),
+        Ok(String::from("alsa_input.usb-Blue_Microphones_Yeti_Stereo_Microphone_REV8-00.analog-stereo"))
+    );
+}
+
+#[test]
+fn meter_isnt_recording() {
+    let outputs = include_str!("fixtures/pactl-list-source-outputs.txt")
+        .replace("application.name = \"Firefox\"", "application.name = \"pulse-status\"");
+    let runner = runner().with_stdout("pactl list source-outputs", &outputs);
+    let block = run(&opts(&["--source"]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "🎙️🔇 120% / 4.75 dB");
+}
+
+#[test]
+fn volume_gradient() {
+    let block = run(&opts(&["--gradient", "0:#268bd2,65:#dc322f"]), None, &runner()).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#dc322f"));
+    let block = run(&opts(&["--source", "--gradient", "0:#268bd2,65:#dc322f"]), None, &runner()).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#859900"));
+    assert!(Opts::try_parse_from(["pulse-status", "--gradient", "0:blue"]).is_err());
+}
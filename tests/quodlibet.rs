@@ -0,0 +1,77 @@
+use clap::Clap;
+use quickshell::quodlibet::{status, volume, StatusOpts, VolumeOpts};
+use quickshell::{ClickEvent, FixtureRunner, WidgetError};
+
+const LONG: &str = "quodlibet --print-playing <title><artist| / <artist>><album| / <album>>";
+const SHORT: &str = "quodlibet --print-playing <title>";
+
+fn runner(status: &str) -> FixtureRunner {
+    FixtureRunner::new()
+        .with_stdout("quodlibet --status", status)
+        .with_stdout(LONG, "Hyperion / Kiasmos / Blurred EP\n")
+        .with_stdout(SHORT, "Hyperion\n")
+}
+
+fn status_opts(args: &[&str]) -> StatusOpts {
+    StatusOpts::parse_from(std::iter::once(&"quodlibet-status").chain(args))
+}
+
+fn volume_opts(args: &[&str]) -> VolumeOpts {
+    VolumeOpts::parse_from(std::iter::once(&"quodlibet-volume").chain(args))
+}
+
+#[test]
+fn playing() {
+    let runner = runner(include_str!("fixtures/quodlibet-status-playing.txt"));
+    let block = status(&status_opts(&[]), None, &runner).unwrap();
+    assert_eq!(block.full_text, "▶ Hyperion / Kiasmos / Blurred EP");
+    assert_eq!(block.short_text.as_deref(), Some("▶ Hyperion"));
+}
+
+#[test]
+fn paused() {
+    let runner = runner(include_str!("fixtures/quodlibet-status-paused.txt"));
+    assert_eq!(status(&status_opts(&[]), None, &runner).unwrap().short_text.as_deref(), Some("⏸️ Hyperion"));
+}
+
+#[test]
+fn stopped() {
+    let runner = runner(include_str!("fixtures/quodlibet-status-stopped.txt"));
+    assert_eq!(status(&status_opts(&[]), None, &runner).unwrap().short_text.as_deref(), Some("⏹️ Hyperion"));
+}
+
+#[test]
+fn not_running() {
+    assert_eq!(
+        status(&status_opts(&[]), None, &FixtureRunner::new()),
+        Err(WidgetError::CommandNotFound(String::from("quodlibet")))
+    );
+}
+
+#[test]
+fn click_next() {
+    let runner = runner(include_str!("fixtures/quodlibet-status-playing.txt"));
+    status(&status_opts(&[]), Some(ClickEvent::button(5)), &runner).unwrap();
+    assert_eq!(runner.calls()[0], "quodlibet --next");
+}
+
+#[test]
+fn volume_levels() {
+    let block = volume(&volume_opts(&[]), None, &runner(include_str!("fixtures/quodlibet-status-playing.txt"))).unwrap();
+    assert_eq!(block.full_text, "🔉 65%");
+    let block = volume(&volume_opts(&[]), None, &runner(include_str!("fixtures/quodlibet-status-paused.txt"))).unwrap();
+    assert_eq!(block.full_text, "🔈 30%");
+}
+
+#[test]
+fn volume_not_running() {
+    assert_eq!(volume(&volume_opts(&[]), None, &FixtureRunner::new()).unwrap().full_text, " ");
+}
+
+#[test]
+fn volume_gradient() {
+    let runner = runner(include_str!("fixtures/quodlibet-status-playing.txt"));
+    let block = volume(&volume_opts(&["--gradient", "0:#268bd2,65:#dc322f"]), None, &runner).unwrap();
+    assert_eq!(block.color.as_deref(), Some("#dc322f"));
+    assert_eq!(volume(&volume_opts(&[]), None, &runner).unwrap().color, None);
+}